@@ -0,0 +1,112 @@
+use crate::entity::EntityId;
+use crate::{entity, parse, scan_lines, Object, Statement, RE};
+use std::collections::{HashMap, HashSet};
+
+static INSTANCE_OF_IRI: &str = "http://www.wikidata.org/prop/direct/P31";
+static SUBCLASS_OF_IRI: &str = "http://www.wikidata.org/prop/direct/P279";
+
+/// Subclass-of edges, from the key of each class to the keys of its direct subclasses
+pub type Subclasses = HashMap<u64, Vec<u64>>;
+
+fn maybe_add_subclass(subclasses: &mut Subclasses, statement: Statement) -> Option<()> {
+    if statement.predicate != SUBCLASS_OF_IRI {
+        return None;
+    }
+    let subclass = entity(statement.subject)?;
+    let Object::IRI(iri) = statement.object else {
+        return None;
+    };
+    let class = EntityId::from_iri(iri)?;
+    subclasses
+        .entry(class.key())
+        .or_default()
+        .push(subclass.key());
+    None
+}
+
+/// Returns the keys of the classes and all their transitive subclasses.
+pub fn closure(classes: &[EntityId], subclasses: &Subclasses) -> HashSet<u64> {
+    let mut closure = HashSet::new();
+    let mut pending: Vec<u64> = classes.iter().map(|class| class.key()).collect();
+    while let Some(class) = pending.pop() {
+        if !closure.insert(class) {
+            continue;
+        }
+        if let Some(children) = subclasses.get(&class) {
+            pending.extend(children);
+        }
+    }
+    closure
+}
+
+/// Returns the keys of the given classes, closed over subclass-of if requested.
+/// The subclass-of edges are read in a separate pass over the dumps.
+pub fn read_classes(paths: &[String], classes: &[EntityId], with_subclasses: bool) -> HashSet<u64> {
+    let mut subclasses = Subclasses::new();
+    if with_subclasses {
+        let needle = format!("<{}>", SUBCLASS_OF_IRI);
+        scan_lines(paths, |number, line| {
+            if line.contains(&needle) {
+                maybe_add_subclass(&mut subclasses, parse(number, line, &RE));
+            }
+        });
+    }
+    closure(classes, &subclasses)
+}
+
+/// Returns whether the entity of a block is an instance of one of the classes.
+pub fn is_instance(statements: &[Statement], classes: &HashSet<u64>) -> bool {
+    statements.iter().any(|statement| {
+        if statement.predicate != INSTANCE_OF_IRI || entity(statement.subject).is_none() {
+            return false;
+        }
+        match statement.object {
+            Object::IRI(iri) => EntityId::from_iri(iri)
+                .map(|class| classes.contains(&class.key()))
+                .unwrap_or(false),
+            _ => false,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::tests::statements;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_closure() {
+        let mut subclasses = Subclasses::new();
+        for line in [
+            r#"<http://www.wikidata.org/entity/Q2> <http://www.wikidata.org/prop/direct/P279> <http://www.wikidata.org/entity/Q1> ."#,
+            r#"<http://www.wikidata.org/entity/Q3> <http://www.wikidata.org/prop/direct/P279> <http://www.wikidata.org/entity/Q2> ."#,
+            r#"<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P279> <http://www.wikidata.org/entity/Q3> ."#,
+            r#"<http://www.wikidata.org/entity/Q4> <http://www.wikidata.org/prop/direct/P279> <http://www.wikidata.org/entity/Q5> ."#,
+            r#"<http://www.wikidata.org/entity/Q6> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q2> ."#,
+        ] {
+            maybe_add_subclass(&mut subclasses, parse(1, line, &RE));
+        }
+
+        let mut classes: Vec<_> = closure(&[EntityId::parse("Q2").unwrap()], &subclasses)
+            .into_iter()
+            .collect();
+        classes.sort();
+        assert_eq!(classes, vec![1, 2, 3]);
+
+        let classes = closure(&[EntityId::parse("Q5").unwrap()], &Subclasses::new());
+        assert_eq!(classes, HashSet::from([5]));
+    }
+
+    #[test]
+    fn test_is_instance() {
+        let statements = statements(&[
+            r#"<http://www.wikidata.org/entity/Q42> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q5> ."#,
+            r#"<http://www.wikidata.org/entity/Q42> <http://www.wikidata.org/prop/direct/P106> <http://www.wikidata.org/entity/Q36180> ."#,
+        ]);
+
+        assert!(is_instance(&statements, &HashSet::from([5])));
+        assert!(!is_instance(&statements, &HashSet::from([36180])));
+    }
+}
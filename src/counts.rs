@@ -0,0 +1,270 @@
+use clap::ValueEnum;
+use crossbeam_channel::{bounded, Sender};
+use std::cmp::Reverse;
+use std::thread::{self, JoinHandle};
+
+const MIN_COMPACT_LEN: usize = 1 << 16;
+
+/// The number of pairs after which a consumer sends its counts to a `CountsMerger`
+pub const SEND_LEN: usize = 1 << 20;
+
+/// Counts keyed by entity key, stored as a compact list of (id, count) pairs.
+///
+/// Counting an id which is the same as the last one just increments the last pair.
+/// This is the common case, as the dump lists all statements of an entity together.
+/// Otherwise a new pair is appended, and once the list has doubled since it was last compacted,
+/// it is sorted and the pairs of equal ids are merged in place.
+///
+/// A pair of a `u64` id takes 16 bytes, so the memory used is bounded by 16 bytes per distinct id,
+/// times two between compactions, e.g. at most about 3.2 GB for 100M entities.
+/// The capacity is shrunk to that bound after each compaction, as merging duplicates
+/// would otherwise leave the list with the capacity of its longest uncompacted state.
+/// Merging moves the pairs of the other counts without copying any keys.
+#[derive(Debug)]
+pub struct Counts<K = u64> {
+    pairs: Vec<(K, u64)>,
+    compacted_len: usize,
+}
+
+impl<K> Default for Counts<K> {
+    fn default() -> Self {
+        Counts {
+            pairs: Vec::new(),
+            compacted_len: 0,
+        }
+    }
+}
+
+impl<K: Ord + Copy> Counts<K> {
+    pub fn increment(&mut self, id: K) {
+        self.add(id, 1)
+    }
+
+    pub fn add(&mut self, id: K, count: u64) {
+        if let Some(last) = self.pairs.last_mut() {
+            if last.0 == id {
+                last.1 += count;
+                return;
+            }
+        }
+        self.pairs.push((id, count));
+        self.maybe_compact();
+    }
+
+    pub fn merge(&mut self, mut other: Counts<K>) {
+        self.pairs.append(&mut other.pairs);
+        self.maybe_compact();
+    }
+
+    fn maybe_compact(&mut self) {
+        if self.pairs.len() >= MIN_COMPACT_LEN.max(self.compacted_len * 2) {
+            self.compact();
+        }
+    }
+
+    fn compact(&mut self) {
+        self.pairs.sort_unstable_by_key(|(id, _)| *id);
+        self.pairs
+            .dedup_by(|(id, count), (previous_id, previous_count)| {
+                if id != previous_id {
+                    return false;
+                }
+                *previous_count += *count;
+                true
+            });
+        self.compacted_len = self.pairs.len();
+        self.pairs
+            .shrink_to(MIN_COMPACT_LEN.max(self.compacted_len * 2));
+    }
+
+    /// Returns the counts, sorted by id.
+    pub fn into_sorted(mut self) -> Vec<(K, u64)> {
+        self.compact();
+        self.pairs.shrink_to_fit();
+        self.pairs
+    }
+}
+
+/// Merges the counts which the consumers send while they run.
+///
+/// Counts of the objects of statements, e.g. the in-degree, include nearly all ids in each
+/// consumer, as the objects are not grouped by consumer like the subjects. Each consumer
+/// therefore sends its counts once they have `SEND_LEN` pairs, so it holds at most that many,
+/// and only the merged counts are bounded by the number of distinct ids.
+pub struct CountsMerger<K> {
+    sender: Sender<Counts<K>>,
+    thread: JoinHandle<Counts<K>>,
+}
+
+impl<K: Ord + Copy + Send + 'static> CountsMerger<K> {
+    pub fn spawn() -> CountsMerger<K> {
+        let (sender, receiver) = bounded::<Counts<K>>(4);
+        let thread = thread::spawn(move || {
+            let mut counts = Counts::default();
+            for other in receiver.iter() {
+                counts.merge(other);
+            }
+            counts
+        });
+        CountsMerger { sender, thread }
+    }
+
+    pub fn sender(&self) -> Sender<Counts<K>> {
+        self.sender.clone()
+    }
+
+    /// Waits for all sent counts to be merged and returns them.
+    pub fn finish(self) -> Counts<K> {
+        drop(self.sender);
+        self.thread.join().unwrap()
+    }
+}
+
+/// Sends the counts to the merger if they have at least `min_len` pairs.
+pub fn maybe_send_counts<K>(
+    counts: Option<&mut Counts<K>>,
+    sender: Option<&Sender<Counts<K>>>,
+    min_len: usize,
+) -> Option<()> {
+    let counts = counts?;
+    if counts.pairs.len() < min_len.max(1) {
+        return None;
+    }
+    sender?.send(std::mem::take(counts)).unwrap();
+    None
+}
+
+#[derive(ValueEnum, Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Order {
+    /// Ascending by entity id
+    Id,
+    /// Descending by count, then ascending by entity id
+    Count,
+}
+
+/// Sorts the counts in the given order, keeping only the top entries by count, if requested.
+pub fn select(mut counts: Vec<(u64, u64)>, order: Order, top: Option<usize>) -> Vec<(u64, u64)> {
+    if order == Order::Count || top.is_some() {
+        counts.sort_unstable_by_key(|(id, count)| (Reverse(*count), *id));
+    }
+    if let Some(top) = top {
+        counts.truncate(top);
+        counts.shrink_to_fit();
+    }
+    if order == Order::Id && top.is_some() {
+        counts.sort_unstable_by_key(|(id, _)| *id);
+    }
+    counts
+}
+
+/// Ranks counts among all counts, independent of the order they are written in.
+pub struct Ranking {
+    descending: Vec<u64>,
+}
+
+impl Ranking {
+    pub fn new(counts: &[(u64, u64)]) -> Ranking {
+        let mut descending: Vec<u64> = counts.iter().map(|(_, count)| *count).collect();
+        descending.sort_unstable_by_key(|count| Reverse(*count));
+        Ranking { descending }
+    }
+
+    /// Returns the rank of the count, starting at 1 for the highest count.
+    /// Equal counts have equal ranks.
+    pub fn rank(&self, count: u64) -> u64 {
+        self.descending.partition_point(|other| *other > count) as u64 + 1
+    }
+
+    /// Returns the percentage of counts which are less than or equal to the count.
+    pub fn percentile(&self, count: u64) -> f64 {
+        let total = self.descending.len() as u64;
+        (total + 1 - self.rank(count)) as f64 * 100.0 / total as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_counts() {
+        let mut first = Counts::default();
+        for id in [3, 3, 1, 3, 2, 2] {
+            first.increment(id);
+        }
+        let mut second = Counts::default();
+        second.increment(2);
+        second.add(4, 5);
+
+        first.merge(second);
+        assert_eq!(first.into_sorted(), vec![(1, 1), (2, 3), (3, 3), (4, 5)]);
+    }
+
+    #[test]
+    fn test_counts_merger() {
+        let merger = CountsMerger::spawn();
+        let sender = merger.sender();
+        let mut counts = Counts::default();
+        for id in [1, 2, 1] {
+            counts.increment(id);
+            maybe_send_counts(Some(&mut counts), Some(&sender), 2);
+        }
+        assert_eq!(counts.pairs, vec![(1, 1)]);
+        maybe_send_counts(Some(&mut counts), Some(&sender), 0);
+        maybe_send_counts(Some(&mut counts), Some(&sender), 0);
+        assert!(counts.pairs.is_empty());
+        drop(sender);
+        assert_eq!(merger.finish().into_sorted(), vec![(1, 2), (2, 1)]);
+    }
+
+    #[test]
+    fn test_counts_compaction() {
+        let mut counts = Counts::default();
+        for round in 0..3 {
+            for id in 0..MIN_COMPACT_LEN as u64 {
+                counts.increment((id * 7919 + round) % MIN_COMPACT_LEN as u64);
+            }
+        }
+        assert!(counts.pairs.len() < MIN_COMPACT_LEN * 2);
+        assert!(counts.pairs.capacity() <= MIN_COMPACT_LEN * 2);
+
+        // merging many duplicates of few ids doesn't keep the capacity of the merged list
+        let mut few = Counts::default();
+        let mut duplicates = Counts::default();
+        for id in 0..MIN_COMPACT_LEN as u64 * 8 {
+            duplicates.pairs.push((id % 16, 1));
+        }
+        few.merge(duplicates);
+        assert_eq!(few.pairs.len(), 16);
+        assert!(few.pairs.capacity() <= MIN_COMPACT_LEN);
+
+        let sorted = counts.into_sorted();
+        assert_eq!(sorted.len(), MIN_COMPACT_LEN);
+        assert!(sorted.iter().all(|(_, count)| *count == 3));
+    }
+
+    #[test]
+    fn test_select_and_rank() {
+        let counts = vec![(1, 5), (2, 10), (3, 5), (4, 1)];
+
+        assert_eq!(select(counts.clone(), Order::Id, None), counts);
+        assert_eq!(
+            select(counts.clone(), Order::Count, None),
+            vec![(2, 10), (1, 5), (3, 5), (4, 1)]
+        );
+        assert_eq!(
+            select(counts.clone(), Order::Id, Some(2)),
+            vec![(1, 5), (2, 10)]
+        );
+
+        let ranking = Ranking::new(&counts);
+        assert_eq!(ranking.rank(10), 1);
+        assert_eq!(ranking.rank(5), 2);
+        assert_eq!(ranking.rank(1), 4);
+        assert_eq!(ranking.percentile(10), 100.0);
+        assert_eq!(ranking.percentile(5), 75.0);
+        assert_eq!(ranking.percentile(1), 25.0);
+    }
+}
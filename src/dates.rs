@@ -0,0 +1,269 @@
+use crate::entity::EntityId;
+use crate::{direct_property, entity, parse_property_range, Extra, Object, Statement};
+use std::fmt::{self, Display, Formatter};
+use std::io::Write;
+use std::str::FromStr;
+
+pub static DATE_TIME_IRI: &str = "http://www.w3.org/2001/XMLSchema#dateTime";
+
+/// A date of the proleptic Gregorian calendar, as precise as the dump gives it.
+///
+/// The dump writes a month or day it doesn't know as `00`, e.g. `-13798000000-00-00T00:00:00Z`.
+/// Truthy statements don't include the precision otherwise, and write a date with year precision
+/// as January 1, e.g. `1952-01-01T00:00:00Z`, so such a date may refer to the whole year.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct Date {
+    /// The year, negative for years before the common era
+    pub year: i64,
+    pub month: Option<u8>,
+    pub day: Option<u8>,
+    /// Whether the date is January 1 at midnight, which may stand for the whole year
+    pub possibly_year: bool,
+}
+
+impl Date {
+    /// The earliest day the date may refer to
+    fn first_day(&self) -> (i64, u8, u8) {
+        (self.year, self.month.unwrap_or(1), self.day.unwrap_or(1))
+    }
+
+    /// The latest day the date may refer to
+    fn last_day(&self) -> (i64, u8, u8) {
+        if self.possibly_year {
+            return (self.year, 12, 31);
+        }
+        (self.year, self.month.unwrap_or(12), self.day.unwrap_or(31))
+    }
+
+    /// The precision of the date: `day`, `month`, `year`, or `day-or-year` for January 1 at midnight
+    fn precision(&self) -> &'static str {
+        match (self.month, self.day) {
+            _ if self.possibly_year => "day-or-year",
+            (None, _) => "year",
+            (Some(_), None) => "month",
+            (Some(_), Some(_)) => "day",
+        }
+    }
+}
+
+/// Parses a date literal, e.g. `+1952-03-11T00:00:00Z`, `-0044-03-15T00:00:00Z` or `2019-08-28`.
+pub fn parse_date(literal: &str) -> Option<Date> {
+    let (negative, rest) = match literal.as_bytes().first()? {
+        b'-' => (true, &literal[1..]),
+        b'+' => (false, &literal[1..]),
+        _ => (false, literal),
+    };
+    let (date, time) = rest.split_once('T').unwrap_or((rest, ""));
+    let mut parts = date.splitn(3, '-');
+    let mut number = |digits: usize| {
+        parts
+            .next()
+            .filter(|part| part.len() >= digits && part.bytes().all(|b| b.is_ascii_digit()))
+    };
+    let year: i64 = number(1)?.parse().ok()?;
+    let month: u8 = number(2)?.parse().ok()?;
+    let day: u8 = number(2)?.parse().ok()?;
+    if month > 12 || day > 31 {
+        return None;
+    }
+    Some(Date {
+        year: if negative { -year } else { year },
+        month: (month > 0).then_some(month),
+        day: (month > 0 && day > 0).then_some(day),
+        possibly_year: month == 1 && day == 1 && time == "00:00:00Z",
+    })
+}
+
+/// Writes the date as precise as it is, e.g. `1952-03-11`, `-0044-03` or `-13798000000`.
+impl Display for Date {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.year < 0 {
+            write!(f, "-")?;
+        }
+        write!(f, "{:04}", self.year.unsigned_abs())?;
+        if let Some(month) = self.month {
+            write!(f, "-{:02}", month)?;
+        }
+        if let Some(day) = self.day {
+            write!(f, "-{:02}", day)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses a date as written by `Display`, or as in the dump.
+impl FromStr for Date {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let padded = match s.trim_start_matches(['-', '+']).matches('-').count() {
+            0 => format!("{}-00-00", s),
+            1 => format!("{}-00", s),
+            _ => s.to_string(),
+        };
+        parse_date(&padded).ok_or_else(|| format!("invalid date: {}", s))
+    }
+}
+
+/// Returns the date of a direct statement with a date value, e.g. of the date of birth (P569).
+fn date<'a>(statement: Statement<'a>) -> Option<(EntityId, &'a str, Date)> {
+    let Object::Literal(literal, Extra::Type(data_type)) = statement.object else {
+        return None;
+    };
+    if data_type != DATE_TIME_IRI {
+        return None;
+    }
+    let entity = entity(statement.subject)?;
+    let property = direct_property(statement.predicate)?;
+    Some((entity, property, parse_date(literal)?))
+}
+
+/// Writes one tab-separated row per date of an entity block: entity, property, date and precision.
+pub fn maybe_write_dates<T: Write>(
+    dates_writer: Option<&mut T>,
+    statements: &[Statement],
+) -> Option<()> {
+    let dates_writer = dates_writer?;
+    for (entity, property, date) in statements.iter().filter_map(|s| date(*s)) {
+        dates_writer
+            .write_fmt(format_args!(
+                "{}\t{}\t{}\t{}\n",
+                entity,
+                property,
+                date,
+                date.precision()
+            ))
+            .unwrap();
+    }
+    None
+}
+
+/// A range of dates of a property, e.g. `P569:1800..` for dates of birth from 1800 on.
+/// Both bounds are optional and inclusive, e.g. `..1900` includes all of 1900.
+/// Dates on January 1 at midnight are taken as possibly the whole year.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DateRange {
+    property: String,
+    from: Option<Date>,
+    to: Option<Date>,
+}
+
+impl DateRange {
+    fn contains(&self, date: Date) -> bool {
+        self.from
+            .is_none_or(|from| date.last_day() >= from.first_day())
+            && self.to.is_none_or(|to| date.first_day() <= to.last_day())
+    }
+
+    /// Returns whether the entity has a date of the property within the range.
+    pub fn contains_entity(&self, statements: &[Statement]) -> bool {
+        statements
+            .iter()
+            .filter_map(|statement| date(*statement))
+            .any(|(_, property, date)| property == self.property && self.contains(date))
+    }
+}
+
+impl FromStr for DateRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (property, from, to) = parse_property_range(s, str::parse::<Date>)?;
+        Ok(DateRange { property, from, to })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::tests::statements;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_parse_date() {
+        let date = |year, month, day| {
+            Some(Date {
+                year,
+                month,
+                day,
+                possibly_year: false,
+            })
+        };
+        assert_eq!(
+            parse_date("+1952-03-11T00:00:00Z"),
+            date(1952, Some(3), Some(11))
+        );
+        assert_eq!(
+            parse_date("-0044-03-15T00:00:00Z"),
+            date(-44, Some(3), Some(15))
+        );
+        assert_eq!(parse_date("2019-08-28"), date(2019, Some(8), Some(28)));
+        assert_eq!(
+            parse_date("-13798000000-00-00T00:00:00Z"),
+            date(-13798000000, None, None)
+        );
+        assert_eq!(
+            parse_date("+1952-01-01T00:00:00Z"),
+            Some(Date {
+                year: 1952,
+                month: Some(1),
+                day: Some(1),
+                possibly_year: true,
+            })
+        );
+        assert_eq!(parse_date("1952-01-01"), date(1952, Some(1), Some(1)));
+        assert_eq!(parse_date("1952-13-01"), None);
+        assert_eq!(parse_date("1952"), None);
+
+        assert_eq!(
+            parse_date("-0044-03-15T00:00:00Z").unwrap().to_string(),
+            "-0044-03-15"
+        );
+        assert_eq!(
+            parse_date("+1952-03-00T00:00:00Z").unwrap().to_string(),
+            "1952-03"
+        );
+        assert_eq!(
+            "-44".parse(),
+            Ok(Date {
+                year: -44,
+                month: None,
+                day: None,
+                possibly_year: false,
+            })
+        );
+    }
+
+    #[test]
+    fn test_dates() {
+        let statements = statements(&[
+            r#"<http://www.wikidata.org/entity/Q42> <http://www.wikidata.org/prop/direct/P569> "1952-03-11T00:00:00Z"^^<http://www.w3.org/2001/XMLSchema#dateTime> ."#,
+            r#"<http://www.wikidata.org/entity/Q42> <http://www.wikidata.org/prop/direct/P570> "2001-05-11T00:00:00Z"^^<http://www.w3.org/2001/XMLSchema#dateTime> ."#,
+            r#"<http://www.wikidata.org/entity/Q42> <http://www.wikidata.org/prop/direct/P26> "1991-01-01T00:00:00Z"^^<http://www.w3.org/2001/XMLSchema#dateTime> ."#,
+            r#"<http://www.wikidata.org/entity/Q42> <http://schema.org/dateModified> "2024-01-01T00:00:00Z"^^<http://www.w3.org/2001/XMLSchema#dateTime> ."#,
+        ]);
+
+        let mut output = Vec::new();
+        maybe_write_dates(Some(&mut output), &statements);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Q42\tP569\t1952-03-11\tday\n\
+             Q42\tP570\t2001-05-11\tday\n\
+             Q42\tP26\t1991-01-01\tday-or-year\n"
+        );
+
+        let range = |s: &str| s.parse::<DateRange>().unwrap();
+        assert!(range("P569:1800..").contains_entity(&statements));
+        assert!(range("P569:1952-03..1952").contains_entity(&statements));
+        assert!(!range("P569:..1951").contains_entity(&statements));
+        assert!(!range("P570:1800..1900").contains_entity(&statements));
+        assert!(!range("P19:1800..").contains_entity(&statements));
+        // January 1 may stand for the whole year
+        assert!(range("P26:1991-06..").contains_entity(&statements));
+        assert!(range("P26:..1991-01-01").contains_entity(&statements));
+        assert!(!range("P26:1992..").contains_entity(&statements));
+        assert!("P569:1800".parse::<DateRange>().is_err());
+        assert!("P569:x..".parse::<DateRange>().is_err());
+    }
+}
@@ -0,0 +1,251 @@
+use clap::ValueEnum;
+use std::fmt;
+use std::str::FromStr;
+
+pub static ENTITY_IRI_PREFIX: &str = "http://www.wikidata.org/entity/";
+pub static ENTITY_DATA_IRI_PREFIX: &str = "https://www.wikidata.org/wiki/Special:EntityData/";
+
+#[derive(ValueEnum, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone)]
+pub enum EntityKind {
+    Item,
+    Property,
+    Lexeme,
+    Form,
+    Sense,
+}
+
+impl EntityKind {
+    pub const ALL: [EntityKind; 5] = [
+        EntityKind::Item,
+        EntityKind::Property,
+        EntityKind::Lexeme,
+        EntityKind::Form,
+        EntityKind::Sense,
+    ];
+
+    fn prefix(self) -> char {
+        match self {
+            EntityKind::Item => 'Q',
+            EntityKind::Property => 'P',
+            EntityKind::Lexeme | EntityKind::Form | EntityKind::Sense => 'L',
+        }
+    }
+
+    fn code(self) -> u64 {
+        match self {
+            EntityKind::Item => 0,
+            EntityKind::Property => 1,
+            EntityKind::Lexeme => 2,
+            EntityKind::Form => 3,
+            EntityKind::Sense => 4,
+        }
+    }
+}
+
+impl fmt::Display for EntityKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            EntityKind::Item => "item",
+            EntityKind::Property => "property",
+            EntityKind::Lexeme => "lexeme",
+            EntityKind::Form => "form",
+            EntityKind::Sense => "sense",
+        };
+        f.write_str(name)
+    }
+}
+
+// Keys pack the kind into the highest bits, followed by the number.
+// Forms and senses pack the number of the lexeme, followed by their own number.
+const KIND_SHIFT: u32 = 61;
+const SUB_BITS: u32 = 20;
+const NUMBER_MASK: u64 = (1 << KIND_SHIFT) - 1;
+const SUB_MASK: u64 = (1 << SUB_BITS) - 1;
+const LEXEME_NUMBER_MASK: u64 = NUMBER_MASK >> SUB_BITS;
+
+/// The id of a Wikibase entity, e.g. `Q42`, `P31`, `L7`, `L7-F2` or `L7-S1`.
+/// Forms and senses have the number of their lexeme and their own number.
+/// Ids whose numbers don't fit into a key are rejected when parsing.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone)]
+pub struct EntityId {
+    pub kind: EntityKind,
+    pub number: u64,
+    pub sub: u64,
+}
+
+impl EntityId {
+    pub fn parse(id: &str) -> Option<EntityId> {
+        let kind = match id.as_bytes().first()? {
+            b'Q' => EntityKind::Item,
+            b'P' => EntityKind::Property,
+            b'L' => EntityKind::Lexeme,
+            _ => return None,
+        };
+        let rest = &id[1..];
+        let (number, sub) = match rest.split_once('-') {
+            Some((number, sub)) if kind == EntityKind::Lexeme => {
+                let (kind, sub) = match sub.as_bytes().first()? {
+                    b'F' => (EntityKind::Form, &sub[1..]),
+                    b'S' => (EntityKind::Sense, &sub[1..]),
+                    _ => return None,
+                };
+                return Some(EntityId {
+                    kind,
+                    number: parse_number(number).filter(|number| *number <= LEXEME_NUMBER_MASK)?,
+                    sub: parse_number(sub).filter(|sub| *sub <= SUB_MASK)?,
+                });
+            }
+            Some(_) => return None,
+            None => (rest, 0),
+        };
+        Some(EntityId {
+            kind,
+            number: parse_number(number).filter(|number| *number <= NUMBER_MASK)?,
+            sub,
+        })
+    }
+
+    pub fn from_iri(iri: &str) -> Option<EntityId> {
+        EntityId::parse(iri.strip_prefix(ENTITY_IRI_PREFIX)?)
+    }
+
+    /// Returns a compact key, which orders like the ids. The keys of items are their numbers.
+    pub fn key(self) -> u64 {
+        let number = match self.kind {
+            EntityKind::Form | EntityKind::Sense => self.number << SUB_BITS | self.sub,
+            _ => self.number,
+        };
+        self.kind.code() << KIND_SHIFT | number & NUMBER_MASK
+    }
+
+    pub fn from_key(key: u64) -> EntityId {
+        let kind = EntityKind::ALL[(key >> KIND_SHIFT) as usize];
+        let number = key & NUMBER_MASK;
+        match kind {
+            EntityKind::Form | EntityKind::Sense => EntityId {
+                kind,
+                number: number >> SUB_BITS,
+                sub: number & SUB_MASK,
+            },
+            _ => EntityId {
+                kind,
+                number,
+                sub: 0,
+            },
+        }
+    }
+
+    /// Returns the id without the leading kind letter, e.g. `42` for `Q42` and `7-F2` for `L7-F2`,
+    /// as written in the outputs next to the kind.
+    pub fn short(self) -> Short {
+        Short(self)
+    }
+}
+
+fn parse_number(number: &str) -> Option<u64> {
+    if number.is_empty() || !number.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    number.parse().ok()
+}
+
+pub struct Short(EntityId);
+
+impl fmt::Display for Short {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let id = self.0;
+        match id.kind {
+            EntityKind::Form => write!(f, "{}-F{}", id.number, id.sub),
+            EntityKind::Sense => write!(f, "{}-S{}", id.number, id.sub),
+            _ => write!(f, "{}", id.number),
+        }
+    }
+}
+
+impl FromStr for EntityId {
+    type Err = String;
+
+    fn from_str(id: &str) -> Result<EntityId, String> {
+        EntityId::parse(id).ok_or_else(|| format!("invalid entity id: {}", id))
+    }
+}
+
+impl fmt::Display for EntityId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.kind.prefix(), self.short())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        for (id, kind) in [
+            ("Q42", EntityKind::Item),
+            ("P31", EntityKind::Property),
+            ("L7", EntityKind::Lexeme),
+            ("L7-F2", EntityKind::Form),
+            ("L7-S1", EntityKind::Sense),
+        ] {
+            let parsed = EntityId::parse(id).unwrap();
+            assert_eq!(parsed.kind, kind);
+            assert_eq!(parsed.to_string(), id);
+            assert_eq!(EntityId::from_key(parsed.key()), parsed);
+        }
+
+        assert_eq!(EntityId::parse("Q42").unwrap().key(), 42);
+        assert_eq!(
+            EntityId::parse("L7-F2").unwrap().short().to_string(),
+            "7-F2"
+        );
+        for id in [
+            "",
+            "Q",
+            "Q-1",
+            "X1",
+            "Q1-F1",
+            "L1-X1",
+            "statement/Q1",
+            "Q2305843009213693952",
+            "L1-F1048576",
+            "L2199023255552-S1",
+        ] {
+            assert_eq!(EntityId::parse(id), None);
+        }
+        assert_eq!(
+            EntityId::from_iri("http://www.wikidata.org/entity/P31"),
+            EntityId::parse("P31")
+        );
+    }
+
+    #[test]
+    fn test_key_range() {
+        for id in ["Q2305843009213693951", "L1-F1048575", "L2199023255551-S1"] {
+            let parsed = EntityId::parse(id).unwrap();
+            assert_eq!(EntityId::from_key(parsed.key()), parsed);
+        }
+    }
+
+    #[test]
+    fn test_key_order() {
+        let ids = [
+            "Q1", "Q20", "P1", "L1", "L2", "L1-F1", "L1-F2", "L2-F1", "L1-S1",
+        ];
+        let mut keys: Vec<_> = ids
+            .iter()
+            .map(|id| EntityId::parse(id).unwrap().key())
+            .collect();
+        keys.sort();
+        let sorted: Vec<_> = keys
+            .into_iter()
+            .map(|key| EntityId::from_key(key).to_string())
+            .collect();
+        assert_eq!(
+            sorted,
+            ["Q1", "Q20", "P1", "L1", "L2", "L1-F1", "L1-F2", "L2-F1", "L1-S1"]
+        );
+    }
+}
@@ -0,0 +1,163 @@
+use crate::classes::is_instance;
+use crate::dates::DateRange;
+use crate::entity::{EntityId, EntityKind, ENTITY_DATA_IRI_PREFIX};
+use crate::geo::Area;
+use crate::quantities::QuantityRange;
+use crate::sitelinks::{sitelink_count, sitelink_wikis};
+use crate::{entity, Statement, Subject};
+use roaring::RoaringTreemap;
+use std::collections::HashSet;
+
+/// Decides which entities are included in the outputs,
+/// based on all statements of the entity's block.
+#[derive(Default)]
+pub struct EntityFilter {
+    pub kinds: HashSet<EntityKind>,
+    /// Only keep entities with a sitelink to one of these wikis, if any
+    pub sitelink_wikis: HashSet<String>,
+    pub min_sitelinks: u64,
+    /// Only keep entities which are instances of one of these classes, if given
+    pub classes: Option<HashSet<u64>>,
+    /// Only keep these entities, if given
+    pub entities: Option<RoaringTreemap>,
+    /// Only keep entities with coordinates within this area, if given
+    pub area: Option<Area>,
+    /// Only keep entities with a date within each of these ranges
+    pub date_ranges: Vec<DateRange>,
+    /// Only keep entities with a numeric value within each of these ranges
+    pub quantity_ranges: Vec<QuantityRange>,
+}
+
+impl EntityFilter {
+    pub fn keep(&self, statements: &[Statement]) -> bool {
+        let Some(id) = block_entity(statements) else {
+            return true;
+        };
+        // the block of a lexeme includes its forms and senses
+        let kind = match id.kind {
+            EntityKind::Lexeme => [EntityKind::Lexeme, EntityKind::Form, EntityKind::Sense]
+                .iter()
+                .any(|kind| self.kinds.contains(kind)),
+            kind => self.kinds.contains(&kind),
+        };
+        kind && self
+            .entities
+            .as_ref()
+            .is_none_or(|entities| entities.contains(id.key()))
+            && self.keep_sitelinks(statements)
+            && self
+                .classes
+                .as_ref()
+                .is_none_or(|classes| is_instance(statements, classes))
+            && self
+                .area
+                .as_ref()
+                .is_none_or(|area| area.contains_entity(statements))
+            && self
+                .date_ranges
+                .iter()
+                .all(|range| range.contains_entity(statements))
+            && self
+                .quantity_ranges
+                .iter()
+                .all(|range| range.contains_entity(statements))
+    }
+
+    /// Returns whether a statement of a kept block is kept,
+    /// which is not the case for forms and senses of a kind which is not included.
+    pub fn keep_statement(&self, statement: Statement) -> bool {
+        entity(statement.subject).is_none_or(|id| {
+            !matches!(id.kind, EntityKind::Form | EntityKind::Sense)
+                || self.kinds.contains(&id.kind)
+        })
+    }
+
+    fn keep_sitelinks(&self, statements: &[Statement]) -> bool {
+        if self.min_sitelinks > 0 && sitelink_count(statements) < self.min_sitelinks {
+            return false;
+        }
+        self.sitelink_wikis.is_empty()
+            || sitelink_wikis(statements)
+                .iter()
+                .any(|wiki| self.sitelink_wikis.contains(wiki))
+    }
+}
+
+/// Returns the entity the statements of an entity block belong to.
+pub fn block_entity(statements: &[Statement]) -> Option<EntityId> {
+    statements.iter().find_map(|statement| {
+        entity(statement.subject).or_else(|| match statement.subject {
+            Subject::IRI(iri) => EntityId::parse(iri.strip_prefix(ENTITY_DATA_IRI_PREFIX)?),
+            Subject::Blank(_) => None,
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::tests::statements;
+
+    #[test]
+    fn test_kinds() {
+        let filter = EntityFilter {
+            kinds: HashSet::from([EntityKind::Item]),
+            ..Default::default()
+        };
+
+        assert!(filter.keep(&statements(&[
+            r#"<https://www.wikidata.org/wiki/Special:EntityData/Q1> <http://schema.org/about> <http://www.wikidata.org/entity/Q1> ."#,
+            r#"<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q5> ."#,
+        ])));
+        assert!(!filter.keep(&statements(&[
+            r#"<https://www.wikidata.org/wiki/Special:EntityData/P31> <http://schema.org/about> <http://www.wikidata.org/entity/P31> ."#,
+            r#"<http://www.wikidata.org/entity/P31> <http://wikiba.se/ontology#propertyType> <http://wikiba.se/ontology#WikibaseItem> ."#,
+        ])));
+        assert!(filter.keep(&statements(&[
+            r#"<http://wikiba.se/ontology#Dump> <http://schema.org/dateModified> "2024-01-01T00:00:00Z"^^<http://www.w3.org/2001/XMLSchema#dateTime> ."#,
+        ])));
+
+        let lexeme = statements(&[
+            r#"<https://www.wikidata.org/wiki/Special:EntityData/L7> <http://schema.org/about> <http://www.wikidata.org/entity/L7> ."#,
+            r#"<http://www.wikidata.org/entity/L7> <http://www.w3.org/ns/lemon/ontolex#lexicalForm> <http://www.wikidata.org/entity/L7-F1> ."#,
+            r#"<http://www.wikidata.org/entity/L7-F1> <http://www.w3.org/ns/lemon/ontolex#representation> "Hund"@de ."#,
+        ]);
+        assert!(!filter.keep(&lexeme));
+        let forms = EntityFilter {
+            kinds: HashSet::from([EntityKind::Form]),
+            ..Default::default()
+        };
+        assert!(forms.keep(&lexeme));
+        assert!(forms.keep_statement(lexeme[2]));
+        let lexemes = EntityFilter {
+            kinds: HashSet::from([EntityKind::Lexeme]),
+            ..Default::default()
+        };
+        assert!(lexemes.keep(&lexeme));
+        assert!(lexemes.keep_statement(lexeme[1]));
+        assert!(!lexemes.keep_statement(lexeme[2]));
+    }
+
+    #[test]
+    fn test_sitelinks() {
+        let statements = statements(&[
+            r#"<https://www.wikidata.org/wiki/Special:EntityData/Q42> <http://schema.org/about> <http://www.wikidata.org/entity/Q42> ."#,
+            r#"<https://www.wikidata.org/wiki/Special:EntityData/Q42> <http://wikiba.se/ontology#sitelinks> "3"^^<http://www.w3.org/2001/XMLSchema#integer> ."#,
+            r#"<https://de.wikipedia.org/wiki/Douglas_Adams> <http://schema.org/about> <http://www.wikidata.org/entity/Q42> ."#,
+            r#"<https://de.wikipedia.org/wiki/Douglas_Adams> <http://schema.org/isPartOf> <https://de.wikipedia.org/> ."#,
+            r#"<https://de.wikipedia.org/wiki/Douglas_Adams> <http://schema.org/name> "Douglas Adams"@de ."#,
+        ]);
+        let filter = |wikis: &[&str], min_sitelinks| EntityFilter {
+            kinds: HashSet::from(EntityKind::ALL),
+            sitelink_wikis: wikis.iter().map(|wiki| wiki.to_string()).collect(),
+            min_sitelinks,
+            ..Default::default()
+        };
+
+        assert!(filter(&[], 0).keep(&statements));
+        assert!(filter(&["enwiki", "dewiki"], 3).keep(&statements));
+        assert!(!filter(&["enwiki"], 0).keep(&statements));
+        assert!(!filter(&[], 4).keep(&statements));
+    }
+}
@@ -0,0 +1,301 @@
+use crate::entity::EntityId;
+use crate::{direct_property, entity, Extra, Object, Statement};
+use serde_json::json;
+use std::io::Write;
+use std::str::FromStr;
+
+static WKT_LITERAL_IRI: &str = "http://www.opengis.net/ont/geosparql#wktLiteral";
+static EARTH: &str = "Q2";
+
+#[derive(Debug, PartialEq)]
+pub struct Coordinates {
+    pub lat: f64,
+    pub lon: f64,
+    /// The globe the coordinates are on, e.g. `Q2` for Earth or `Q405` for the Moon
+    pub globe: EntityId,
+}
+
+impl Coordinates {
+    pub fn is_on_earth(&self) -> bool {
+        self.globe.to_string() == EARTH
+    }
+}
+
+/// Parses a WKT point, e.g. `Point(13.38 52.52)`.
+/// Points which are not on Earth are prefixed with the IRI of their globe, e.g.
+/// `<http://www.wikidata.org/entity/Q405> Point(-141.6 42.6)`.
+pub fn parse_point(wkt: &str) -> Option<Coordinates> {
+    let (globe, point) = match wkt.strip_prefix('<') {
+        Some(rest) => {
+            let (iri, point) = rest.split_once('>')?;
+            (EntityId::from_iri(iri)?, point.trim_start())
+        }
+        None => (EntityId::parse(EARTH)?, wkt),
+    };
+    let point = point
+        .get(..6)
+        .filter(|prefix| prefix.eq_ignore_ascii_case("point("))
+        .and_then(|_| point[6..].strip_suffix(')'))?;
+    let (lon, lat) = point.trim().split_once(char::is_whitespace)?;
+    Some(Coordinates {
+        lat: lat.trim().parse().ok()?,
+        lon: lon.parse().ok()?,
+        globe,
+    })
+}
+
+/// Returns the coordinates of a direct statement, e.g. of the coordinate location (P625).
+fn coordinates<'a>(statement: Statement<'a>) -> Option<(EntityId, &'a str, Coordinates)> {
+    let Object::Literal(wkt, Extra::Type(data_type)) = statement.object else {
+        return None;
+    };
+    if data_type != WKT_LITERAL_IRI {
+        return None;
+    }
+    let entity = entity(statement.subject)?;
+    let property = direct_property(statement.predicate)?;
+    Some((entity, property, parse_point(wkt)?))
+}
+
+/// Writes one tab-separated row per coordinates of an entity block:
+/// entity, property, latitude, longitude and globe.
+pub fn maybe_write_coordinates<T: Write>(
+    coordinates_writer: Option<&mut T>,
+    statements: &[Statement],
+) -> Option<()> {
+    let coordinates_writer = coordinates_writer?;
+    for (entity, property, coordinates) in statements.iter().filter_map(|s| coordinates(*s)) {
+        coordinates_writer
+            .write_fmt(format_args!(
+                "{}\t{}\t{}\t{}\t{}\n",
+                entity, property, coordinates.lat, coordinates.lon, coordinates.globe
+            ))
+            .unwrap();
+    }
+    None
+}
+
+/// Writes one GeoJSON point feature per line for the coordinates on Earth of an entity block.
+/// GeoJSON coordinates are always on Earth, so the coordinates on other globes are skipped.
+pub fn maybe_write_features<T: Write>(
+    features_writer: Option<&mut T>,
+    statements: &[Statement],
+) -> Option<()> {
+    let features_writer = features_writer?;
+    let earth_coordinates = statements
+        .iter()
+        .filter_map(|s| coordinates(*s))
+        .filter(|(_, _, coordinates)| coordinates.is_on_earth());
+    for (entity, property, coordinates) in earth_coordinates {
+        let feature = json!({
+            "type": "Feature",
+            "geometry": {
+                "type": "Point",
+                "coordinates": [coordinates.lon, coordinates.lat],
+            },
+            "properties": {
+                "id": entity.to_string(),
+                "property": property,
+            },
+        });
+        serde_json::to_writer(&mut *features_writer, &feature).unwrap();
+        features_writer.write_all(b"\n").unwrap();
+    }
+    None
+}
+
+/// An area on Earth, given as longitudes and latitudes
+#[derive(Debug, Clone, PartialEq)]
+pub enum Area {
+    BoundingBox {
+        min_lon: f64,
+        min_lat: f64,
+        max_lon: f64,
+        max_lat: f64,
+    },
+    /// The outer ring of a polygon, as (longitude, latitude) points
+    Polygon(Vec<(f64, f64)>),
+}
+
+impl Area {
+    pub fn contains(&self, lon: f64, lat: f64) -> bool {
+        match self {
+            Area::BoundingBox {
+                min_lon,
+                min_lat,
+                max_lon,
+                max_lat,
+            } => (*min_lon..=*max_lon).contains(&lon) && (*min_lat..=*max_lat).contains(&lat),
+            Area::Polygon(points) => {
+                // ray casting: count the edges crossed by a ray from the point to the east
+                let mut inside = false;
+                for (index, &(lon1, lat1)) in points.iter().enumerate() {
+                    let (lon2, lat2) = points[(index + 1) % points.len()];
+                    if (lat1 > lat) != (lat2 > lat)
+                        && lon < (lon2 - lon1) * (lat - lat1) / (lat2 - lat1) + lon1
+                    {
+                        inside = !inside;
+                    }
+                }
+                inside
+            }
+        }
+    }
+
+    /// Parses a WKT polygon, e.g. `POLYGON((5.9 47.3, 15.0 47.3, 15.0 55.1, 5.9 55.1, 5.9 47.3))`.
+    /// Polygons with holes, i.e. with inner rings, are rejected.
+    pub fn parse_polygon(wkt: &str) -> Result<Area, String> {
+        let wkt = wkt.trim();
+        let rings = wkt
+            .get(..7)
+            .filter(|prefix| prefix.eq_ignore_ascii_case("polygon"))
+            .and_then(|_| wkt[7..].trim_start().strip_prefix('('))
+            .and_then(|rest| rest.strip_suffix(')'))
+            .ok_or_else(|| format!("expected a WKT polygon: {}", wkt))?;
+        let ring = rings
+            .trim()
+            .strip_prefix('(')
+            .and_then(|rest| rest.strip_suffix(')'))
+            .ok_or_else(|| format!("expected a WKT polygon: {}", wkt))?;
+        if ring.contains(['(', ')']) {
+            return Err(format!("polygons with holes are not supported: {}", wkt));
+        }
+        let points = ring
+            .split(',')
+            .map(|point| {
+                let mut parts = point.split_whitespace().map(f64::from_str);
+                match (parts.next(), parts.next(), parts.next()) {
+                    (Some(Ok(lon)), Some(Ok(lat)), None) => Ok((lon, lat)),
+                    _ => Err(format!("invalid point: {}", point.trim())),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if points.len() < 3 {
+            return Err("a polygon needs at least three points".to_string());
+        }
+        Ok(Area::Polygon(points))
+    }
+
+    /// Returns whether the entity has coordinates on Earth within the area.
+    pub fn contains_entity(&self, statements: &[Statement]) -> bool {
+        statements
+            .iter()
+            .filter_map(|statement| coordinates(*statement))
+            .any(|(_, _, coordinates)| {
+                coordinates.is_on_earth() && self.contains(coordinates.lon, coordinates.lat)
+            })
+    }
+}
+
+/// Parses a bounding box, e.g. `5.9,47.3,15.0,55.1`
+/// for the minimum and maximum longitude and latitude.
+impl FromStr for Area {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values = s
+            .split(',')
+            .map(|value| value.trim().parse::<f64>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| format!("invalid bounding box {}: {}", s, err))?;
+        let [min_lon, min_lat, max_lon, max_lat] = values[..] else {
+            return Err(format!(
+                "expected min_lon,min_lat,max_lon,max_lat, got {}",
+                s
+            ));
+        };
+        if min_lon > max_lon || min_lat > max_lat {
+            return Err(format!(
+                "the minimum is greater than the maximum in the bounding box {}, \
+                 bounding boxes across the antimeridian are not supported",
+                s
+            ));
+        }
+        Ok(Area::BoundingBox {
+            min_lon,
+            min_lat,
+            max_lon,
+            max_lat,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::tests::statements;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_parse_point() {
+        assert_eq!(
+            parse_point("Point(13.38 52.52)"),
+            Some(Coordinates {
+                lat: 52.52,
+                lon: 13.38,
+                globe: EntityId::parse("Q2").unwrap()
+            })
+        );
+        assert_eq!(
+            parse_point("<http://www.wikidata.org/entity/Q405> Point(-141.6 42.6)"),
+            Some(Coordinates {
+                lat: 42.6,
+                lon: -141.6,
+                globe: EntityId::parse("Q405").unwrap()
+            })
+        );
+        assert_eq!(parse_point("Point(13.38)"), None);
+        assert_eq!(parse_point("LINESTRING(1 2, 3 4)"), None);
+    }
+
+    #[test]
+    fn test_products() {
+        let statements = statements(&[
+            r#"<http://www.wikidata.org/entity/Q64> <http://www.wikidata.org/prop/direct/P625> "Point(13.38 52.52)"^^<http://www.opengis.net/ont/geosparql#wktLiteral> ."#,
+            r#"<http://www.wikidata.org/entity/Q64> <http://www.wikidata.org/prop/direct/P1082> "+3755251"^^<http://www.w3.org/2001/XMLSchema#decimal> ."#,
+            r#"<http://www.wikidata.org/entity/Q64> <http://www.wikidata.org/prop/direct/P625> "<http://www.wikidata.org/entity/Q405> Point(-141.6 42.6)"^^<http://www.opengis.net/ont/geosparql#wktLiteral> ."#,
+        ]);
+
+        let mut output = Vec::new();
+        maybe_write_coordinates(Some(&mut output), &statements);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Q64\tP625\t52.52\t13.38\tQ2\n\
+             Q64\tP625\t42.6\t-141.6\tQ405\n"
+        );
+
+        let mut output = Vec::new();
+        maybe_write_features(Some(&mut output), &statements);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "{\"geometry\":{\"coordinates\":[13.38,52.52],\"type\":\"Point\"},\
+             \"properties\":{\"id\":\"Q64\",\"property\":\"P625\"},\
+             \"type\":\"Feature\"}\n"
+        );
+    }
+
+    #[test]
+    fn test_area() {
+        let statements = statements(&[
+            r#"<http://www.wikidata.org/entity/Q64> <http://www.wikidata.org/prop/direct/P625> "Point(13.38 52.52)"^^<http://www.opengis.net/ont/geosparql#wktLiteral> ."#,
+        ]);
+        let germany: Area = "5.9,47.3,15.0,55.1".parse().unwrap();
+        assert!(germany.contains_entity(&statements));
+        assert!(!germany.contains_entity(&statements[..0]));
+
+        let triangle = Area::parse_polygon("POLYGON ((10 50, 20 50, 10 60, 10 50))").unwrap();
+        assert!(triangle.contains(12.0, 52.0));
+        assert!(!triangle.contains(18.0, 58.0));
+        assert!(!triangle.contains_entity(&statements[..0]));
+
+        assert!(Area::parse_polygon("POINT(1 2)").is_err());
+        assert!(Area::parse_polygon(
+            "POLYGON((0 0, 10 0, 10 10, 0 10, 0 0), (2 2, 4 2, 4 4, 2 2))"
+        )
+        .is_err());
+        assert!("1,2,3".parse::<Area>().is_err());
+        assert!("170,-10,-170,10".parse::<Area>().is_err());
+        assert!("0,10,1,-10".parse::<Area>().is_err());
+    }
+}
@@ -0,0 +1,160 @@
+use crate::entity::{EntityId, EntityKind};
+use crate::{
+    direct_property, entity, identifier_properties, identifier_property_iris, parse, scan_lines,
+    unescape, Object, Statement, RE,
+};
+use std::collections::HashSet;
+use std::io::Write;
+
+static DIRECT_NORMALIZED_PROPERTY_IRI_PREFIX: &str =
+    "http://www.wikidata.org/prop/direct-normalized/";
+static PROPERTY_TYPE_IRI: &str = "http://wikiba.se/ontology#propertyType";
+static EXTERNAL_ID_IRI: &str = "http://wikiba.se/ontology#ExternalId";
+
+#[derive(Debug, PartialEq, Eq)]
+struct Identifier<'a> {
+    entity: EntityId,
+    property: &'a str,
+    value: String,
+    normalized: Option<&'a str>,
+}
+
+/// Returns the number of the property, if the statement declares it as an external identifier.
+fn external_id_property(statement: Statement) -> Option<u64> {
+    if statement.predicate != PROPERTY_TYPE_IRI || statement.object != Object::IRI(EXTERNAL_ID_IRI)
+    {
+        return None;
+    }
+    let property = entity(statement.subject)?;
+    (property.kind == EntityKind::Property).then_some(property.number)
+}
+
+/// Returns the direct and normalized predicates of all external identifier properties
+/// declared in the dumps, so the identifiers always match the dump version.
+pub fn read_identifier_properties(paths: &[String]) -> HashSet<String> {
+    let needle = format!("<{}>", EXTERNAL_ID_IRI);
+    let mut properties = Vec::new();
+    scan_lines(paths, |number, line| {
+        if line.contains(&needle) {
+            properties.extend(external_id_property(parse(number, line, &RE)));
+        }
+    });
+    identifier_property_iris(properties.into_iter())
+}
+
+/// Returns the external identifiers of an entity block.
+/// The dump lists the normalized IRI of an identifier, if any, right after its raw value.
+fn identifiers<'a>(statements: &[Statement<'a>]) -> Vec<Identifier<'a>> {
+    let mut identifiers: Vec<Identifier> = Vec::new();
+    let mut previous = None;
+    for (index, statement) in statements.iter().enumerate() {
+        if !identifier_properties().contains(statement.predicate) {
+            continue;
+        }
+        let Some(entity) = entity(statement.subject) else {
+            continue;
+        };
+        match statement.object {
+            Object::Literal(value, _) => {
+                let Some(property) = direct_property(statement.predicate) else {
+                    continue;
+                };
+                identifiers.push(Identifier {
+                    entity,
+                    property,
+                    value: unescape(value),
+                    normalized: None,
+                });
+                previous = Some(index);
+            }
+            Object::IRI(iri) => {
+                let Some(property) = statement
+                    .predicate
+                    .strip_prefix(DIRECT_NORMALIZED_PROPERTY_IRI_PREFIX)
+                else {
+                    continue;
+                };
+                let Some(identifier) = identifiers.last_mut() else {
+                    continue;
+                };
+                if previous == index.checked_sub(1)
+                    && identifier.entity == entity
+                    && identifier.property == property
+                {
+                    identifier.normalized = Some(iri);
+                }
+            }
+            Object::Blank(_) => (),
+        }
+    }
+    identifiers
+}
+
+/// Writes one tab-separated row per external identifier of an entity block:
+/// entity, property, raw value, and normalized IRI, if any.
+pub fn maybe_write_identifiers<T: Write>(
+    identifiers_writer: Option<&mut T>,
+    statements: &[Statement],
+) -> Option<()> {
+    let identifiers_writer = identifiers_writer?;
+    for identifier in identifiers(statements) {
+        identifiers_writer
+            .write_fmt(format_args!(
+                "{}\t{}\t{}\t{}\n",
+                identifier.entity,
+                identifier.property,
+                identifier.value,
+                identifier.normalized.unwrap_or_default()
+            ))
+            .unwrap();
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::tests::statements;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_identifiers() {
+        let statements = statements(&[
+            r#"<http://www.wikidata.org/entity/Q8> <http://www.wikidata.org/prop/direct/P508> "13163" ."#,
+            r#"<http://www.wikidata.org/entity/Q8> <http://www.wikidata.org/prop/direct-normalized/P508> <http://purl.org/bncf/tid/13163> ."#,
+            r#"<http://www.wikidata.org/entity/Q8> <http://www.wikidata.org/prop/direct/P910> <http://www.wikidata.org/entity/Q8505256> ."#,
+            r#"<http://www.wikidata.org/entity/Q8> <http://www.wikidata.org/prop/direct/P1296> "0108463" ."#,
+            r#"<http://www.wikidata.org/entity/Q8> <http://www.wikidata.org/prop/direct/P1296> "0183409" ."#,
+            r#"<http://www.wikidata.org/entity/Q8> <http://www.wikidata.org/prop/direct/P227> "4021325-0" ."#,
+            r#"<http://www.wikidata.org/entity/Q8> <http://www.wikidata.org/prop/direct-normalized/P227> <https://d-nb.info/gnd/4021325-0> ."#,
+        ]);
+
+        let mut output = Vec::new();
+        maybe_write_identifiers(Some(&mut output), &statements);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Q8\tP508\t13163\thttp://purl.org/bncf/tid/13163\n\
+             Q8\tP1296\t0108463\t\n\
+             Q8\tP1296\t0183409\t\n\
+             Q8\tP227\t4021325-0\thttps://d-nb.info/gnd/4021325-0\n"
+        );
+    }
+
+    #[test]
+    fn test_external_id_property() {
+        let property = |line| external_id_property(parse(1, line, &RE));
+        assert_eq!(
+            property(
+                r#"<http://www.wikidata.org/entity/P214> <http://wikiba.se/ontology#propertyType> <http://wikiba.se/ontology#ExternalId> ."#
+            ),
+            Some(214)
+        );
+        assert_eq!(
+            property(
+                r#"<http://www.wikidata.org/entity/P31> <http://wikiba.se/ontology#propertyType> <http://wikiba.se/ontology#WikibaseItem> ."#
+            ),
+            None
+        );
+    }
+}
@@ -0,0 +1,139 @@
+use crate::entity::EntityId;
+use crate::{parse, Object, Statement, RE};
+use bzip2::bufread::BzDecoder;
+use bzip2::write::BzEncoder;
+use bzip2::Compression;
+use clap::ValueEnum;
+use roaring::RoaringTreemap;
+use std::fs::{rename, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::thread;
+
+#[derive(ValueEnum, Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Dangling {
+    /// Remove the triples from the outputs
+    Drop,
+    /// Keep the triples, but also write them to `dangling_<n>.bz2`
+    Report,
+}
+
+/// Returns whether the statement refers to an entity which is not included.
+/// The prefix is the one of entity IRIs in the outputs, which may have been rewritten.
+fn is_dangling(statement: Statement, prefix: &str, entities: &RoaringTreemap) -> bool {
+    let Object::IRI(iri) = statement.object else {
+        return false;
+    };
+    iri.strip_prefix(prefix)
+        .and_then(EntityId::parse)
+        .is_some_and(|id| !entities.contains(id.key()))
+}
+
+/// Drops or reports the triples of the output file which refer to entities that are not included.
+/// Returns the number of dangling triples.
+fn check_file(
+    path: &str,
+    report_path: &str,
+    prefix: &str,
+    entities: &RoaringTreemap,
+    dangling: Dangling,
+) -> u64 {
+    let file = File::open(path).unwrap_or_else(|_| panic!("can't open file: {}", path));
+    let reader = BufReader::new(BzDecoder::new(BufReader::new(file)));
+
+    let output_path = match dangling {
+        Dangling::Drop => format!("{}.tmp", path),
+        Dangling::Report => report_path.to_string(),
+    };
+    let output_file = File::create(&output_path)
+        .unwrap_or_else(|_| panic!("unable to create file: {}", &output_path));
+    let mut encoder = BzEncoder::new(BufWriter::new(output_file), Compression::best());
+
+    let mut count = 0;
+    for (line, number) in reader.lines().zip(1u64..) {
+        let line = line.unwrap();
+        let is_dangling = is_dangling(parse(number, &line, &RE), prefix, entities);
+        if is_dangling {
+            count += 1;
+        }
+        if is_dangling == (dangling == Dangling::Report) {
+            encoder.write_all(line.as_bytes()).unwrap();
+            encoder.write_all(b"\n").unwrap();
+        }
+    }
+    encoder.try_finish().unwrap();
+    drop(encoder);
+
+    if dangling == Dangling::Drop {
+        rename(&output_path, path)
+            .unwrap_or_else(|err| panic!("unable to replace file {}: {}", path, err));
+    }
+    count
+}
+
+/// Checks the output files in parallel, after the main pass has determined the included entities.
+/// The other products are already written by then, so they are not adjusted.
+pub fn check_references(
+    names: &[String],
+    prefix: &str,
+    entities: &RoaringTreemap,
+    dangling: Dangling,
+) {
+    let count: u64 = thread::scope(|scope| {
+        let threads: Vec<_> = names
+            .iter()
+            .map(|name| {
+                scope.spawn(move || {
+                    check_file(
+                        &format!("{}.nt.bz2", name),
+                        &format!("dangling_{}.bz2", name),
+                        prefix,
+                        entities,
+                        dangling,
+                    )
+                })
+            })
+            .collect();
+        threads
+            .into_iter()
+            .map(|thread| thread.join().unwrap())
+            .sum()
+    });
+    eprintln!("# dangling references: {}", count);
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::entity::ENTITY_IRI_PREFIX;
+
+    #[test]
+    fn test_is_dangling() {
+        let entities: RoaringTreemap = [1, 5].into_iter().collect();
+        let dangling = |line| is_dangling(parse(1, line, &RE), ENTITY_IRI_PREFIX, &entities);
+
+        assert!(!dangling(
+            r#"<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q5> ."#
+        ));
+        assert!(dangling(
+            r#"<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P279> <http://www.wikidata.org/entity/Q6> ."#
+        ));
+        assert!(dangling(
+            r#"<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P1343> <http://www.wikidata.org/entity/L5> ."#
+        ));
+        assert!(!dangling(
+            r#"<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P1476> "Title"@en ."#
+        ));
+        assert!(!dangling(
+            r#"<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P856> <https://example.com/> ."#
+        ));
+
+        let line = r#"<https://example.org/Q1> <http://www.wikidata.org/prop/direct/P279> <https://example.org/Q6> ."#;
+        assert!(!dangling(line));
+        assert!(is_dangling(
+            parse(1, line, &RE),
+            "https://example.org/",
+            &entities
+        ));
+    }
+}
@@ -0,0 +1,384 @@
+use crate::entity::EntityId;
+use crate::{term, unescape, Object, Statement, Term};
+use memmap2::Mmap;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+
+// The store is a header, followed by fixed-size records sorted by entity,
+// followed by the text of all records:
+//
+// header: magic (8 bytes), record count (u64)
+// record: entity (u64), text offset (u64), text length (u32), term (u8), language length (u8), padding (2 bytes)
+// text:   language followed by the unescaped label, for each record
+//
+// While the dump is processed, each consumer writes its entries in sorted runs to disk,
+// which are merged into the store at the end:
+//
+// run entry: entity (u64), term (u8), language length (u8), text length (u32), language, text
+
+const MAGIC: &[u8; 8] = b"WDLABEL1";
+const HEADER_SIZE: usize = 16;
+const RECORD_SIZE: usize = 24;
+
+/// The number of entries a consumer keeps in memory before writing them to a run
+pub const RUN_ENTRIES: usize = 1_000_000;
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct LabelEntry {
+    id: u64,
+    term: Term,
+    lang: String,
+    text: String,
+}
+
+/// The entries of a consumer, written in sorted runs to files next to the store
+pub struct LabelRuns {
+    prefix: String,
+    limit: usize,
+    entries: Vec<LabelEntry>,
+    paths: Vec<String>,
+}
+
+impl LabelRuns {
+    pub fn new(store_path: &str, name: &str, limit: usize) -> LabelRuns {
+        LabelRuns {
+            prefix: format!("{}.{}", store_path, name),
+            limit,
+            entries: Vec::new(),
+            paths: Vec::new(),
+        }
+    }
+
+    fn add(&mut self, entry: LabelEntry) {
+        self.entries.push(entry);
+        if self.entries.len() >= self.limit {
+            self.write_run();
+        }
+    }
+
+    fn write_run(&mut self) {
+        if self.entries.is_empty() {
+            return;
+        }
+        self.entries.sort_unstable();
+        let path = format!("{}.{}.run", self.prefix, self.paths.len());
+        let file =
+            File::create(&path).unwrap_or_else(|_| panic!("unable to create file: {}", path));
+        let mut writer = BufWriter::new(file);
+        for entry in self.entries.drain(..) {
+            writer.write_all(&entry.id.to_le_bytes()).unwrap();
+            writer
+                .write_all(&[term_code(entry.term), entry.lang.len() as u8])
+                .unwrap();
+            writer
+                .write_all(&(entry.text.len() as u32).to_le_bytes())
+                .unwrap();
+            writer.write_all(entry.lang.as_bytes()).unwrap();
+            writer.write_all(entry.text.as_bytes()).unwrap();
+        }
+        writer.flush().unwrap();
+        self.paths.push(path);
+    }
+
+    /// Writes the remaining entries and returns the paths of all runs.
+    pub fn finish(mut self) -> Vec<String> {
+        self.write_run();
+        self.paths
+    }
+}
+
+fn read_run_entry<R: Read>(reader: &mut R) -> Option<LabelEntry> {
+    let mut header = [0u8; 14];
+    match reader.read_exact(&mut header) {
+        Ok(()) => (),
+        Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return None,
+        Err(err) => panic!("failed to read label run: {}", err),
+    }
+    let lang_length = header[9] as usize;
+    let text_length = u32::from_le_bytes(header[10..14].try_into().unwrap()) as usize;
+    let mut text = vec![0u8; lang_length + text_length];
+    reader.read_exact(&mut text).expect("truncated label run");
+    let text = String::from_utf8(text).expect("invalid label run text");
+    Some(LabelEntry {
+        id: u64::from_le_bytes(header[..8].try_into().unwrap()),
+        term: code_term(header[8]),
+        lang: text[..lang_length].to_string(),
+        text: text[lang_length..].to_string(),
+    })
+}
+
+pub fn maybe_add_label_entry(
+    runs: Option<&mut LabelRuns>,
+    id: EntityId,
+    statement: Statement,
+) -> Option<()> {
+    let runs = runs?;
+    let (term, lang) = term(statement)?;
+    let Object::Literal(text, _) = statement.object else {
+        return None;
+    };
+    runs.add(LabelEntry {
+        id: id.key(),
+        term,
+        lang: lang.to_string(),
+        text: unescape(text),
+    });
+    None
+}
+
+fn term_code(term: Term) -> u8 {
+    match term {
+        Term::Label => 0,
+        Term::Alias => 1,
+        Term::Description => 2,
+    }
+}
+
+fn code_term(code: u8) -> Term {
+    match code {
+        0 => Term::Label,
+        1 => Term::Alias,
+        2 => Term::Description,
+        _ => panic!("invalid label store term: {}", code),
+    }
+}
+
+/// Merges the sorted runs into the store, removes them and returns the number of entries.
+/// The records are written while merging, and the text is collected in a temporary file
+/// which is appended at the end.
+pub fn write_label_store(path: &str, runs: &[String]) -> u64 {
+    let mut readers: Vec<_> = runs
+        .iter()
+        .map(|run| {
+            BufReader::new(File::open(run).unwrap_or_else(|_| panic!("can't open file: {}", run)))
+        })
+        .collect();
+    let mut heap = BinaryHeap::new();
+    for (index, reader) in readers.iter_mut().enumerate() {
+        if let Some(entry) = read_run_entry(reader) {
+            heap.push(Reverse((entry, index)));
+        }
+    }
+
+    let file = File::create(path).unwrap_or_else(|_| panic!("unable to create file: {}", path));
+    let mut writer = BufWriter::new(file);
+    let text_path = format!("{}.text", path);
+    let text_file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&text_path)
+        .unwrap_or_else(|_| panic!("unable to create file: {}", text_path));
+    let mut text_writer = BufWriter::new(text_file);
+
+    writer.write_all(MAGIC).unwrap();
+    // the record count is only known after merging
+    writer.write_all(&0u64.to_le_bytes()).unwrap();
+
+    let mut count = 0u64;
+    let mut offset = 0u64;
+    while let Some(Reverse((entry, index))) = heap.pop() {
+        let length = entry.lang.len() + entry.text.len();
+        writer.write_all(&entry.id.to_le_bytes()).unwrap();
+        writer.write_all(&offset.to_le_bytes()).unwrap();
+        writer.write_all(&(length as u32).to_le_bytes()).unwrap();
+        writer
+            .write_all(&[term_code(entry.term), entry.lang.len() as u8, 0, 0])
+            .unwrap();
+        text_writer.write_all(entry.lang.as_bytes()).unwrap();
+        text_writer.write_all(entry.text.as_bytes()).unwrap();
+        offset += length as u64;
+        count += 1;
+
+        if let Some(entry) = read_run_entry(&mut readers[index]) {
+            heap.push(Reverse((entry, index)));
+        }
+    }
+
+    let mut text_file = text_writer.into_inner().unwrap();
+    text_file.seek(SeekFrom::Start(0)).unwrap();
+    io::copy(&mut text_file, &mut writer).unwrap();
+    let mut file = writer.into_inner().unwrap();
+    file.seek(SeekFrom::Start(MAGIC.len() as u64)).unwrap();
+    file.write_all(&count.to_le_bytes()).unwrap();
+
+    fs::remove_file(&text_path).unwrap();
+    for run in runs {
+        fs::remove_file(run).unwrap();
+    }
+    count
+}
+
+pub struct LabelStore {
+    data: Mmap,
+    count: usize,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Label<'a> {
+    pub term: Term,
+    pub lang: &'a str,
+    pub text: &'a str,
+}
+
+impl LabelStore {
+    /// Opens the store and checks that the file is as long as its header says.
+    pub fn open(path: &str) -> Result<LabelStore, String> {
+        let file = File::open(path).map_err(|err| format!("can't open file {}: {}", path, err))?;
+        let data = unsafe { Mmap::map(&file) }
+            .map_err(|err| format!("unable to map file {}: {}", path, err))?;
+        if data.len() < HEADER_SIZE || &data[..8] != MAGIC {
+            return Err(format!("not a label store: {}", path));
+        }
+        let count = u64::from_le_bytes(data[8..16].try_into().unwrap()) as usize;
+        let text_start = count
+            .checked_mul(RECORD_SIZE)
+            .and_then(|size| size.checked_add(HEADER_SIZE))
+            .filter(|text_start| *text_start <= data.len())
+            .ok_or_else(|| format!("truncated label store {}: {} records", path, count))?;
+        let store = LabelStore { data, count };
+        // the records are written in the order of their text, so the last one ends the file
+        let text_length = match count {
+            0 => 0,
+            _ => {
+                let record = store.record(count - 1);
+                u64::from_le_bytes(record[8..16].try_into().unwrap())
+                    + u32::from_le_bytes(record[16..20].try_into().unwrap()) as u64
+            }
+        };
+        if (text_start as u64).checked_add(text_length) != Some(store.data.len() as u64) {
+            return Err(format!(
+                "truncated label store {}: expected {} bytes of text, got {}",
+                path,
+                text_length,
+                store.data.len() - text_start
+            ));
+        }
+        Ok(store)
+    }
+
+    fn record(&self, index: usize) -> &[u8] {
+        let start = HEADER_SIZE + index * RECORD_SIZE;
+        &self.data[start..start + RECORD_SIZE]
+    }
+
+    fn record_id(&self, index: usize) -> u64 {
+        u64::from_le_bytes(self.record(index)[..8].try_into().unwrap())
+    }
+
+    fn label(&self, index: usize) -> Label<'_> {
+        let record = self.record(index);
+        let text_start = HEADER_SIZE + self.count * RECORD_SIZE;
+        let offset = u64::from_le_bytes(record[8..16].try_into().unwrap()) as usize;
+        let length = u32::from_le_bytes(record[16..20].try_into().unwrap()) as usize;
+        let lang_length = record[21] as usize;
+        let start = text_start + offset;
+        let text = std::str::from_utf8(&self.data[start..start + length])
+            .expect("invalid label store text");
+        Label {
+            term: code_term(record[20]),
+            lang: &text[..lang_length],
+            text: &text[lang_length..],
+        }
+    }
+
+    /// Returns all labels, aliases and descriptions of the entity with the given key.
+    pub fn get(&self, id: u64) -> impl Iterator<Item = Label<'_>> {
+        let mut low = 0;
+        let mut high = self.count;
+        while low < high {
+            let middle = (low + high) / 2;
+            if self.record_id(middle) < id {
+                low = middle + 1;
+            } else {
+                high = middle;
+            }
+        }
+        (low..self.count)
+            .take_while(move |index| self.record_id(*index) == id)
+            .map(|index| self.label(index))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn entry(id: u64, term: Term, lang: &str, text: &str) -> LabelEntry {
+        LabelEntry {
+            id,
+            term,
+            lang: lang.to_string(),
+            text: text.to_string(),
+        }
+    }
+
+    /// Writes a store of the entries, as two consumers with runs of two entries would
+    fn write_test_store(path: &str, entries: Vec<LabelEntry>) {
+        let mut first = LabelRuns::new(path, "1", 2);
+        let mut second = LabelRuns::new(path, "2", 2);
+        for (index, entry) in entries.into_iter().enumerate() {
+            if index % 2 == 0 {
+                first.add(entry);
+            } else {
+                second.add(entry);
+            }
+        }
+        let mut runs = first.finish();
+        runs.extend(second.finish());
+        write_label_store(path, &runs);
+        assert!(runs.iter().all(|run| !std::path::Path::new(run).exists()));
+    }
+
+    #[test]
+    fn test_store() {
+        let path =
+            std::env::temp_dir().join(format!("wikidata-filter-{}.labels", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        write_test_store(
+            path,
+            vec![
+                entry(42, Term::Label, "en", "Douglas Adams"),
+                entry(8, Term::Alias, "en", "joy"),
+                entry(42, Term::Description, "en", "English writer"),
+                entry(8, Term::Label, "en", "happiness"),
+                entry(1, Term::Label, "de", "Universum"),
+            ],
+        );
+
+        let store = LabelStore::open(path).unwrap();
+        assert_eq!(
+            store.get(8).collect::<Vec<_>>(),
+            vec![
+                Label {
+                    term: Term::Label,
+                    lang: "en",
+                    text: "happiness"
+                },
+                Label {
+                    term: Term::Alias,
+                    lang: "en",
+                    text: "joy"
+                },
+            ]
+        );
+        assert_eq!(store.get(1).count(), 1);
+        assert_eq!(store.get(42).count(), 2);
+        assert_eq!(store.get(2).count(), 0);
+        assert_eq!(store.get(43).count(), 0);
+        drop(store);
+
+        let data = fs::read(path).unwrap();
+        for length in [HEADER_SIZE + RECORD_SIZE, data.len() - 1] {
+            fs::write(path, &data[..length]).unwrap();
+            assert!(LabelStore::open(path).is_err());
+        }
+
+        fs::remove_file(path).unwrap();
+    }
+}
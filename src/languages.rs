@@ -0,0 +1,175 @@
+use crate::{Extra, Object, Statement};
+use std::borrow::Cow;
+use std::collections::HashSet;
+
+/// A set of BCP 47 language tags, which are matched case-insensitively.
+///
+/// The entry `*` matches any language, and an entry like `zh-*` matches `zh`
+/// and all of its variants, e.g. `zh-hant` and `zh-Hant-TW`.
+#[derive(Debug, Default)]
+pub struct LanguageSet {
+    any: bool,
+    exact: HashSet<String>,
+    prefixes: Vec<String>,
+}
+
+impl LanguageSet {
+    pub fn parse<'a, I: IntoIterator<Item = &'a str>>(entries: I) -> LanguageSet {
+        let mut set = LanguageSet::default();
+        for entry in entries {
+            let entry = entry.trim().to_ascii_lowercase();
+            if entry.is_empty() {
+                continue;
+            }
+            if entry == "*" {
+                set.any = true;
+            } else if let Some(prefix) = entry.strip_suffix("-*") {
+                set.prefixes.push(prefix.to_string());
+            } else {
+                set.exact.insert(entry);
+            }
+        }
+        set
+    }
+
+    pub fn contains(&self, lang: &str) -> bool {
+        if self.any {
+            return true;
+        }
+        let lang = if lang.bytes().any(|b| b.is_ascii_uppercase()) {
+            Cow::Owned(lang.to_ascii_lowercase())
+        } else {
+            Cow::Borrowed(lang)
+        };
+        self.exact.contains(lang.as_ref())
+            || self.prefixes.iter().any(|prefix| {
+                lang.strip_prefix(prefix.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('-'))
+            })
+    }
+
+    /// Returns the languages listed without a wildcard, in lower case.
+    pub fn exact(&self) -> impl Iterator<Item = &str> {
+        self.exact.iter().map(String::as_str)
+    }
+}
+
+/// Returns whether the subtag is a region subtag, i.e. two letters or three digits.
+fn is_region(subtag: &str) -> bool {
+    (subtag.len() == 2 && subtag.bytes().all(|b| b.is_ascii_alphabetic()))
+        || (subtag.len() == 3 && subtag.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Returns the tag in the BCP 47 case conventions, e.g. `zh-Hant-TW` for `zh-hant-tw`:
+/// the language in lower case, scripts in title case, and regions in upper case.
+/// Subtags after a singleton, e.g. a private use `x-…`, are in lower case.
+pub fn normalize(tag: &str) -> String {
+    let mut normalized = String::with_capacity(tag.len());
+    let mut extension = false;
+    for (index, subtag) in tag.split('-').enumerate() {
+        if index > 0 {
+            normalized.push('-');
+        }
+        if index == 0 || extension {
+            normalized.push_str(&subtag.to_ascii_lowercase());
+        } else if subtag.len() == 1 {
+            extension = true;
+            normalized.push_str(&subtag.to_ascii_lowercase());
+        } else if is_region(subtag) {
+            normalized.push_str(&subtag.to_ascii_uppercase());
+        } else if subtag.len() == 4 && subtag.bytes().all(|b| b.is_ascii_alphabetic()) {
+            normalized.push_str(&subtag[..1].to_ascii_uppercase());
+            normalized.push_str(&subtag[1..].to_ascii_lowercase());
+        } else {
+            normalized.push_str(&subtag.to_ascii_lowercase());
+        }
+    }
+    normalized
+}
+
+/// Returns the tag without its region, e.g. `en` for `en-gb` and `zh-hant` for `zh-hant-tw`.
+pub fn without_region(tag: &str) -> String {
+    let mut subtags = Vec::new();
+    let mut extension = false;
+    for (index, subtag) in tag.split('-').enumerate() {
+        extension |= index > 0 && subtag.len() == 1;
+        if index > 0 && !extension && is_region(subtag) {
+            continue;
+        }
+        subtags.push(subtag);
+    }
+    subtags.join("-")
+}
+
+/// Returns the N-Triples line of the statement with its language tag rewritten,
+/// if it has one and the rewrite changes it.
+pub fn rewrite_lang(statement: Statement, base: bool, normalized: bool) -> Option<String> {
+    let Object::Literal(literal, Extra::Lang(lang)) = statement.object else {
+        return None;
+    };
+    let mut rewritten = lang.to_string();
+    if base {
+        rewritten = without_region(&rewritten);
+    }
+    if normalized {
+        rewritten = normalize(&rewritten);
+    }
+    if rewritten == lang {
+        return None;
+    }
+    let statement = Statement {
+        object: Object::Literal(literal, Extra::Lang(&rewritten)),
+        ..statement
+    };
+    Some(format!("{}\n", statement))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{parse, RE};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_language_set() {
+        let set = LanguageSet::parse(["en", "DE", "zh-*", ""]);
+        assert!(set.contains("en"));
+        assert!(set.contains("EN"));
+        assert!(set.contains("de"));
+        assert!(set.contains("zh"));
+        assert!(set.contains("zh-Hant-TW"));
+        assert!(!set.contains("en-gb"));
+        assert!(!set.contains("zhx"));
+        assert!(LanguageSet::parse(["*"]).contains("tlh"));
+
+        let mut exact: Vec<_> = set.exact().collect();
+        exact.sort();
+        assert_eq!(exact, vec!["de", "en"]);
+    }
+
+    #[test]
+    fn test_tags() {
+        assert_eq!(normalize("zh-hant-tw"), "zh-Hant-TW");
+        assert_eq!(normalize("EN-gb"), "en-GB");
+        assert_eq!(normalize("es-419"), "es-419");
+        assert_eq!(normalize("de-x-ab"), "de-x-ab");
+        assert_eq!(without_region("en-gb"), "en");
+        assert_eq!(without_region("zh-hant-tw"), "zh-hant");
+        assert_eq!(without_region("de-x-ch"), "de-x-ch");
+
+        let line =
+            r#"<http://www.wikidata.org/entity/Q8> <http://schema.org/name> "happiness"@en-gb ."#;
+        assert_eq!(
+            rewrite_lang(parse(1, line, &RE), true, true).as_deref(),
+            Some(
+                "<http://www.wikidata.org/entity/Q8> <http://schema.org/name> \"happiness\"@en .\n"
+            )
+        );
+        assert_eq!(
+            rewrite_lang(parse(1, line, &RE), false, true).as_deref(),
+            Some("<http://www.wikidata.org/entity/Q8> <http://schema.org/name> \"happiness\"@en-GB .\n")
+        );
+        assert_eq!(rewrite_lang(parse(1, line, &RE), false, false), None);
+    }
+}
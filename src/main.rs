@@ -1,16 +1,42 @@
 use bzip2::bufread::BzDecoder;
 use bzip2::write::BzEncoder;
 use bzip2::Compression;
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use counts::{maybe_send_counts, Counts, CountsMerger, Order, Ranking, SEND_LEN};
 use crossbeam_channel::{bounded, unbounded, Receiver, Sender};
+use dates::{maybe_write_dates, DateRange};
+use entity::{EntityId, EntityKind, ENTITY_IRI_PREFIX};
+use filter::{block_entity, EntityFilter};
+use geo::{maybe_write_coordinates, maybe_write_features, Area};
+use identifiers::maybe_write_identifiers;
+use integrity::Dangling;
+use label_store::{maybe_add_label_entry, write_label_store, LabelRuns, RUN_ENTRIES};
+use languages::{rewrite_lang, LanguageSet};
 use lazy_static::lazy_static;
+use property_catalog::{maybe_add_property_entry, write_property_catalog, PropertyEntry};
+use property_stats::{
+    maybe_add_property_stats, merge_property_statistics, write_property_statistics,
+    PropertyStatistics,
+};
+use quantities::{maybe_write_quantities, QuantityRange};
 use regex::Regex;
-use std::collections::{HashMap, HashSet};
+use rewrite::Rewrites;
+use roaring::RoaringTreemap;
+use rules::{Action, Rules};
+use sanitize::{Outcome, Reason, SanitizeAction, Sanitized, Sanitizer, Store};
+use script::Script;
+use search::{SearchIndex, SearchOpts};
+use serve::ServeOpts;
+use sitelinks::maybe_write_sitelinks;
+use sqlite::{maybe_add_row, statement_count_row, Row, SqliteWriter, BATCH_ROWS};
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::fmt::{self, Display, Formatter};
 use std::fs::File;
 use std::io::{BufRead, BufReader, BufWriter, Read, Write};
 use std::process::exit;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 use std::thread;
 use std::time::Instant;
 
@@ -20,13 +46,175 @@ const PROGRESS_COUNT: u64 = 100000;
 #[macro_use]
 extern crate lazy_static_include;
 
+mod classes;
+mod counts;
+mod dates;
+mod entity;
+mod filter;
+mod geo;
+mod identifiers;
+mod integrity;
+mod label_store;
+mod languages;
+mod property_catalog;
+mod property_stats;
+mod quantities;
+mod rewrite;
+mod rules;
+mod sanitize;
+mod script;
+mod search;
+mod serve;
+mod sitelinks;
+mod sqlite;
+mod subgraph;
+
 #[derive(Parser)]
 #[clap(author, version, about, long_about = None)]
+#[clap(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Opts {
+    #[clap(subcommand)]
+    command: Option<Command>,
     #[clap(long)]
     labels: bool,
     #[clap(long)]
     statement_counts: bool,
+    /// The order of the statement counts
+    #[clap(long, value_enum, default_value = "id")]
+    statement_counts_order: Order,
+    /// Add the rank and percentile of each entity by statement count
+    #[clap(long)]
+    statement_counts_rank: bool,
+    /// Only write the statement counts of the given number of entities with the most statements
+    #[clap(long)]
+    statement_counts_top: Option<usize>,
+    /// Write the sitelinks of each entity: entity, wiki, title and URL
+    #[clap(long)]
+    sitelinks: bool,
+    /// Only write the sitelinks to these wikis, e.g. `enwiki`
+    #[clap(long, value_delimiter = ',')]
+    sitelinks_wikis: Vec<String>,
+    /// Write the external identifiers of each entity, which are excluded from the RDF output:
+    /// entity, property, raw value and normalized IRI
+    #[clap(long)]
+    identifiers: bool,
+    /// Write the coordinates of each entity: entity, property, latitude, longitude and globe
+    #[clap(long)]
+    coordinates: bool,
+    /// Write the coordinates on Earth of each entity as GeoJSON point features, one per line
+    #[clap(long)]
+    coordinates_geojson: bool,
+    /// Write the dates of each entity: entity, property, date, as precise as the dump gives it,
+    /// and precision, `day-or-year` for January 1, as truthy statements write years
+    #[clap(long)]
+    dates: bool,
+    /// Write the numeric values of each entity, e.g. populations: entity, property and value
+    #[clap(long)]
+    quantities: bool,
+    /// Read the identifier properties from the input dumps in an additional pass,
+    /// instead of using the included list
+    #[clap(long)]
+    derive_identifier_properties: bool,
+    /// Read the identifier properties from this dump, e.g. a properties-only extract,
+    /// instead of using the included list
+    #[clap(long, conflicts_with = "derive_identifier_properties")]
+    identifier_properties_dump: Option<String>,
+    /// Decide which triples are written with the rules in this file, instead of the default rules
+    #[clap(long)]
+    rules: Option<String>,
+    /// Transform each triple which the rules keep with the `transform` function of this Rhai script
+    #[clap(long)]
+    script: Option<String>,
+    /// Rewrite the IRIs of each triple which the rules keep with the rewrite rules in this file,
+    /// before the script transforms it
+    #[clap(long)]
+    rewrite: Option<String>,
+    /// Check the literals and IRIs of each written triple against the limits of this triple store,
+    /// after the rewrites and the script
+    #[clap(long, value_enum)]
+    sanitize: Option<Store>,
+    /// What to do with the triples the triple store would reject
+    #[clap(long, value_enum, default_value = "repair")]
+    sanitize_action: SanitizeAction,
+    /// The languages of labels, aliases and descriptions, instead of the included list,
+    /// e.g. `en,de,zh-*`, or `*` for any language. The search index only includes the languages
+    /// listed without a wildcard
+    #[clap(long, value_delimiter = ',')]
+    langs: Vec<String>,
+    /// Write language tags in the BCP 47 case conventions, e.g. `en-GB` for `en-gb`
+    #[clap(long)]
+    normalize_langs: bool,
+    /// Write language tags without their region, e.g. `en` for `en-gb`
+    #[clap(long)]
+    base_langs: bool,
+    /// The kinds of entities to include
+    #[clap(long, value_enum, value_delimiter = ',', default_values_t = EntityKind::ALL)]
+    entity_kinds: Vec<EntityKind>,
+    /// Only include entities with a sitelink to one of these wikis, e.g. `enwiki`
+    #[clap(long, value_delimiter = ',')]
+    require_sitelinks: Vec<String>,
+    /// Only include entities with at least this number of sitelinks
+    #[clap(long, default_value = "0")]
+    min_sitelinks: u64,
+    /// Only include entities which are instances of one of these classes, e.g. `Q5`
+    #[clap(long, value_delimiter = ',')]
+    classes: Vec<EntityId>,
+    /// Also include instances of subclasses of the classes.
+    /// The subclasses are read in an additional pass over the dumps
+    #[clap(long)]
+    subclasses: bool,
+    /// Only include the seed entities and the entities linked from them within `--hops` hops
+    #[clap(long, value_delimiter = ',')]
+    seeds: Vec<EntityId>,
+    /// The number of hops from the seeds. Each hop is an additional pass over the dumps
+    #[clap(long, default_value = "1")]
+    hops: usize,
+    /// Only include entities with coordinates on Earth within this bounding box,
+    /// given as `min_lon,min_lat,max_lon,max_lat`, which may not cross the antimeridian
+    #[clap(long, allow_hyphen_values = true)]
+    bbox: Option<Area>,
+    /// Only include entities with coordinates on Earth within the WKT polygon in this file,
+    /// which may not have holes
+    #[clap(long, conflicts_with = "bbox")]
+    polygon: Option<String>,
+    /// Only include entities with a date of the property within the range, e.g. `P569:1800..`
+    /// for people born from 1800 on, or `P571:-0500..0500`. Both bounds are optional and inclusive,
+    /// and January 1 is taken as possibly the whole year
+    #[clap(long, allow_hyphen_values = true)]
+    date_range: Vec<DateRange>,
+    /// Only include entities with a numeric value of the property within the range,
+    /// e.g. `P1082:1000000..` for a population of at least a million. Both bounds are optional
+    /// and inclusive
+    #[clap(long, allow_hyphen_values = true)]
+    quantity_range: Vec<QuantityRange>,
+    /// Drop or report the triples referring to entities which are not included,
+    /// in a second pass over the RDF outputs. The other products, e.g. the statement counts,
+    /// the in-degree and the labels, are written before and still include these triples
+    #[clap(long, value_enum)]
+    dangling: Option<Dangling>,
+    /// Write usage statistics of each direct property
+    #[clap(long)]
+    property_stats: bool,
+    /// Write a catalog of all properties with their datatype, English label, formatter URL,
+    /// and whether they are identifiers, as JSON and CSV
+    #[clap(long)]
+    property_catalog: bool,
+    /// Write the number of kept statements referring to each entity
+    #[clap(long)]
+    in_degree: bool,
+    /// Also write the number of direct statements referring to each entity, per property
+    #[clap(long)]
+    in_degree_by_property: bool,
+    /// Write a full-text search index of labels, aliases and descriptions to this directory
+    #[clap(long)]
+    index: Option<String>,
+    /// Write labels, aliases, descriptions and statement counts to this new SQLite database
+    #[clap(long)]
+    sqlite: Option<String>,
+    /// Write a compact label store for the `serve` command to this file.
+    /// The labels are written in sorted runs next to the file, which are merged at the end of the run
+    #[clap(long)]
+    label_store: Option<String>,
     #[clap(short, long, default_value = "0")]
     skip: u64,
     #[clap(short, long)]
@@ -35,6 +223,14 @@ struct Opts {
     paths: Vec<String>,
 }
 
+#[derive(Subcommand)]
+enum Command {
+    /// Search an index written with --index
+    Search(SearchOpts),
+    /// Serve labels from a label store written with --label-store over HTTP on localhost
+    Serve(ServeOpts),
+}
+
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
 pub enum Extra<'a> {
     None,
@@ -42,7 +238,7 @@ pub enum Extra<'a> {
     Lang(&'a str),
 }
 
-#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
 pub enum Subject<'a> {
     IRI(&'a str),
     Blank(&'a str),
@@ -62,13 +258,126 @@ pub struct Statement<'a> {
     object: Object<'a>,
 }
 
+// The N-Triples serialization. IRIs are written as they are, except for characters which are
+// not allowed in IRIs, which are percent-encoded. Blank node labels are written as they are.
+// Literals are escaped canonically, e.g. `"a \"b\""`, no matter how the dump escaped them,
+// and a backslash which doesn't start a valid escape sequence is written as an escaped backslash.
+
+/// Writes an IRI, with the characters which are not allowed in IRIs percent-encoded.
+fn write_iri(f: &mut Formatter<'_>, iri: &str) -> fmt::Result {
+    write!(f, "<{}>", encode_iri(iri))
+}
+
+impl Display for Extra<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Extra::None => Ok(()),
+            Extra::Type(data_type) => {
+                write!(f, "^^")?;
+                write_iri(f, data_type)
+            }
+            Extra::Lang(lang) => write!(f, "@{}", lang),
+        }
+    }
+}
+
+impl Display for Subject<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Subject::IRI(iri) => write_iri(f, iri),
+            Subject::Blank(blank) => write!(f, "_:{}", blank),
+        }
+    }
+}
+
+impl Display for Object<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Object::IRI(iri) => write_iri(f, iri),
+            Object::Blank(blank) => write!(f, "_:{}", blank),
+            Object::Literal(literal, extra) => {
+                let escaped = match try_unescape(literal) {
+                    Ok(unescaped) => escape(&unescaped),
+                    Err(_) => escape(literal),
+                };
+                write!(f, "\"{}\"{}", escaped, extra)
+            }
+        }
+    }
+}
+
+/// Writes the statement as an N-Triples line, without the line break.
+impl Display for Statement<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ", self.subject)?;
+        write_iri(f, self.predicate)?;
+        write!(f, " {} .", self.object)
+    }
+}
+
 pub enum Work {
     LINES(u64, Vec<String>),
     DONE,
 }
 
 pub struct WorkResult {
-    statement_counts: Option<HashMap<String, u64>>,
+    statement_counts: Option<Counts>,
+    label_runs: Option<Vec<String>>,
+    property_stats: Option<PropertyStatistics>,
+    property_entries: Option<Vec<PropertyEntry>>,
+    entities: Option<RoaringTreemap>,
+    sanitized: Option<Counts<(Reason, Outcome)>>,
+    /// The error which stopped the consumer, e.g. of the script
+    error: Option<String>,
+}
+
+/// The outputs each consumer produces in addition to the filtered lines
+#[derive(Clone, Default)]
+struct Outputs {
+    labels: bool,
+    sitelinks: bool,
+    sitelinks_wikis: Arc<HashSet<String>>,
+    identifiers: bool,
+    coordinates: bool,
+    coordinates_geojson: bool,
+    dates: bool,
+    quantities: bool,
+    statement_counts: bool,
+    label_store: Option<String>,
+    property_stats: bool,
+    property_catalog: bool,
+    in_degree: Option<Sender<Counts>>,
+    in_degree_by_property: Option<Sender<Counts<(u64, u64)>>>,
+    entities: bool,
+    index: Option<Arc<SearchIndex>>,
+    rewrites: Option<Arc<Rewrites>>,
+    script: Option<Arc<Script>>,
+    sanitizer: Option<Sanitizer>,
+    normalize_langs: bool,
+    base_langs: bool,
+    sqlite: Option<Sender<Vec<Row>>>,
+}
+
+/// The products of a consumer, gathered while handling statements
+#[derive(Default)]
+struct Products<T: Write> {
+    labels: Option<T>,
+    sitelinks: Option<T>,
+    identifiers: Option<T>,
+    coordinates: Option<T>,
+    coordinates_geojson: Option<T>,
+    dates: Option<T>,
+    quantities: Option<T>,
+    quarantine: Option<T>,
+    statement_counter: Option<Counts>,
+    rows: Option<Vec<Row>>,
+    label_runs: Option<LabelRuns>,
+    property_stats: Option<PropertyStatistics>,
+    property_entries: Option<Vec<PropertyEntry>>,
+    in_degree: Option<Counts>,
+    in_degree_by_property: Option<Counts<(u64, u64)>>,
+    entities: Option<RoaringTreemap>,
+    sanitized: Option<Counts<(Reason, Outcome)>>,
 }
 
 lazy_static! {
@@ -115,7 +424,7 @@ lazy_static! {
           # literal
           (?:
 
-            "([^"]*)"
+            "((?:[^"\\]|\\.)*)"
 
             # optional extra
             (?:
@@ -177,6 +486,7 @@ lazy_static_include_str! {
     IDENTIFIER_PROPERTIES_DATA => "identifier-properties",
     LANGUAGES_DATA => "languages",
     LABELS_DATA => "labels",
+    RULES_DATA => "rules",
 }
 
 lazy_static! {
@@ -184,29 +494,116 @@ lazy_static! {
 }
 
 lazy_static! {
-    static ref IDENTIFIER_PROPERTIES: HashSet<String> = line_set(&IDENTIFIER_PROPERTIES_DATA)
-        .iter()
-        .flat_map(|id| vec![
+    static ref IDENTIFIER_PROPERTIES: HashSet<String> =
+        identifier_property_iris(line_set(&IDENTIFIER_PROPERTIES_DATA).into_iter());
+}
+
+/// The identifier properties read from the dump, which replace the included ones if set
+static DUMP_IDENTIFIER_PROPERTIES: OnceLock<HashSet<String>> = OnceLock::new();
+
+fn identifier_properties() -> &'static HashSet<String> {
+    DUMP_IDENTIFIER_PROPERTIES
+        .get()
+        .unwrap_or(&IDENTIFIER_PROPERTIES)
+}
+
+fn identifier_property_iris<I: Iterator<Item = T>, T: Display>(ids: I) -> HashSet<String> {
+    ids.flat_map(|id| {
+        vec![
             format!("http://www.wikidata.org/prop/direct/P{}", id),
-            format!("http://www.wikidata.org/prop/direct-normalized/P{}", id)
-        ])
-        .collect();
+            format!("http://www.wikidata.org/prop/direct-normalized/P{}", id),
+        ]
+    })
+    .collect()
 }
 
 lazy_static! {
-    static ref LANGUAGES: HashSet<&'static str> = line_set(&LANGUAGES_DATA);
+    static ref LANGUAGES: LanguageSet = LanguageSet::parse(LANGUAGES_DATA.lines());
+}
+
+/// The languages given as an option, which replace the included ones if set
+static CUSTOM_LANGUAGES: OnceLock<LanguageSet> = OnceLock::new();
+
+fn languages() -> &'static LanguageSet {
+    CUSTOM_LANGUAGES.get().unwrap_or(&LANGUAGES)
 }
 
 lazy_static! {
     static ref LABELS: HashSet<&'static str> = line_set(&LABELS_DATA);
 }
 
+/// The rules which decide whether a triple is written, which replace the default rules if set
+static CUSTOM_RULES: OnceLock<Rules> = OnceLock::new();
+
+lazy_static! {
+    static ref DEFAULT_RULES: Rules =
+        Rules::parse(&RULES_DATA).unwrap_or_else(|err| panic!("invalid default rules: {}", err));
+}
+
+fn rules() -> &'static Rules {
+    CUSTOM_RULES.get().unwrap_or(&DEFAULT_RULES)
+}
+
 fn line_set(data: &str) -> HashSet<&str> {
     data.lines().collect()
 }
 
-fn ignored_subject(iri: &str) -> bool {
-    iri.starts_with("https://www.wikidata.org/wiki/Special:EntityData")
+static ENTITY_BLOCK_PREFIXES: [&str; 2] = [
+    "<http://www.wikidata.org/entity/",
+    "<https://www.wikidata.org/wiki/Special:EntityData/",
+];
+
+/// Returns the local name of the entity the line starts a block for, if any.
+/// The dump lists all triples of an entity together, starting with its entity data,
+/// and lines with other subjects (e.g. sitelinks) belong to the current block.
+/// Forms and senses belong to the block of their lexeme, e.g. `L7-F2` to `L7`.
+fn block_key(line: &str) -> Option<&str> {
+    let rest = ENTITY_BLOCK_PREFIXES
+        .iter()
+        .find_map(|prefix| line.strip_prefix(prefix))?;
+    let key = &rest[..rest.find('>')?];
+    if key.contains('/') {
+        return None;
+    }
+    Some(key.split_once('-').map_or(key, |(lexeme, _)| lexeme))
+}
+
+fn entity_blocks(lines: &[String]) -> Vec<&[String]> {
+    let mut blocks = Vec::new();
+    let mut start = 0;
+    let mut current = None;
+    for (index, line) in lines.iter().enumerate() {
+        let key = block_key(line);
+        if key.is_none() || key == current {
+            continue;
+        }
+        if index > start {
+            blocks.push(&lines[start..index]);
+            start = index;
+        }
+        current = key;
+    }
+    if start < lines.len() {
+        blocks.push(&lines[start..]);
+    }
+    blocks
+}
+
+/// Calls the function with each line of the dumps, on a single thread.
+/// Used for passes over the dumps which gather data for the main pass.
+fn scan_lines<F: FnMut(u64, &str)>(paths: &[String], mut f: F) {
+    for path in paths {
+        eprintln!("# scanning {}", path);
+        let file = File::open(path).expect("can't open file");
+        let mut reader = BufReader::new(BzDecoder::new(BufReader::new(file)));
+        let mut line = String::new();
+        let mut number = 0;
+        while reader.read_line(&mut line).unwrap() > 0 {
+            number += 1;
+            f(number, &line);
+            line.clear();
+        }
+    }
 }
 
 fn produce<T: Read>(
@@ -219,6 +616,7 @@ fn produce<T: Read>(
     let mut buf_reader = BufReader::new(reader);
 
     let mut lines = Vec::new();
+    let mut current_block = String::new();
 
     if skip > 0 {
         eprintln!("# skipping {}", skip)
@@ -226,7 +624,7 @@ fn produce<T: Read>(
 
     loop {
         if !running.load(Ordering::SeqCst) {
-            eprintln!("# interrupted after {}", total);
+            eprintln!("# stopped after {}", total);
             return (false, total);
         }
 
@@ -239,12 +637,19 @@ fn produce<T: Read>(
         let skipped = total < skip;
 
         if !skipped {
-            lines.push(line);
-
-            if total % BATCH_SIZE == 0 {
-                s.send(Work::LINES(total, lines)).unwrap();
-                lines = Vec::new();
+            // only cut batches at entity block boundaries,
+            // so each entity is handled by a single consumer
+            if let Some(key) = block_key(&line) {
+                if key != current_block {
+                    if lines.len() as u64 >= BATCH_SIZE {
+                        s.send(Work::LINES(total - 1, lines)).unwrap();
+                        lines = Vec::new();
+                    }
+                    current_block = key.to_string();
+                }
             }
+
+            lines.push(line);
         }
 
         if total % PROGRESS_COUNT == 0 {
@@ -260,66 +665,188 @@ fn produce<T: Read>(
     (true, total)
 }
 
+fn create_encoder(path: &str) -> BzEncoder<BufWriter<File>> {
+    let file = File::create(path).unwrap_or_else(|_| panic!("unable to create file: {}", path));
+    BzEncoder::new(BufWriter::new(file), Compression::best())
+}
+
 fn consume(
     name: String,
     work_receiver: Receiver<Work>,
     result_sender: Sender<WorkResult>,
-    labels: bool,
-    statement_counts: bool,
+    outputs: Outputs,
+    filter: Arc<EntityFilter>,
+    running: Arc<AtomicBool>,
 ) {
     let regex = RE.clone();
-
-    let lines_path = format!("{}.nt.bz2", name);
-    let lines_file = File::create(&lines_path)
-        .unwrap_or_else(|_| panic!("unable to create file: {}", &lines_path));
-    let mut lines_encoder = BzEncoder::new(BufWriter::new(lines_file), Compression::best());
-
-    let mut labels_encoder = if labels {
-        let labels_path = format!("labels_{}.bz2", name);
-        let labels_file = File::create(&labels_path)
-            .unwrap_or_else(|_| panic!("unable to create file: {}", &labels_path));
-        Some(BzEncoder::new(
-            BufWriter::new(labels_file),
-            Compression::best(),
-        ))
-    } else {
-        None
-    };
-
-    let mut statement_counter = if statement_counts {
-        Some(HashMap::new())
-    } else {
-        None
+    let mut error = None;
+
+    let mut lines_encoder = create_encoder(&format!("{}.nt.bz2", name));
+
+    let mut products = Products {
+        labels: outputs
+            .labels
+            .then(|| create_encoder(&format!("labels_{}.bz2", name))),
+        sitelinks: outputs
+            .sitelinks
+            .then(|| create_encoder(&format!("sitelinks_{}.bz2", name))),
+        identifiers: outputs
+            .identifiers
+            .then(|| create_encoder(&format!("identifiers_{}.bz2", name))),
+        coordinates: outputs
+            .coordinates
+            .then(|| create_encoder(&format!("coordinates_{}.bz2", name))),
+        coordinates_geojson: outputs
+            .coordinates_geojson
+            .then(|| create_encoder(&format!("coordinates_{}.geojsonl.bz2", name))),
+        dates: outputs
+            .dates
+            .then(|| create_encoder(&format!("dates_{}.bz2", name))),
+        quantities: outputs
+            .quantities
+            .then(|| create_encoder(&format!("quantities_{}.bz2", name))),
+        quarantine: outputs
+            .sanitizer
+            .filter(|sanitizer| sanitizer.action == SanitizeAction::Quarantine)
+            .map(|_| create_encoder(&format!("quarantine_{}.nt.bz2", name))),
+        statement_counter: outputs.statement_counts.then(Counts::default),
+        rows: None,
+        label_runs: outputs
+            .label_store
+            .as_ref()
+            .map(|path| LabelRuns::new(path, &name, RUN_ENTRIES)),
+        property_stats: outputs.property_stats.then(PropertyStatistics::new),
+        property_entries: outputs.property_catalog.then(Vec::new),
+        in_degree: outputs.in_degree.as_ref().map(|_| Counts::default()),
+        in_degree_by_property: outputs
+            .in_degree_by_property
+            .as_ref()
+            .map(|_| Counts::default()),
+        entities: outputs.entities.then(RoaringTreemap::new),
+        sanitized: outputs.sanitizer.map(|_| Counts::default()),
     };
 
     loop {
         match work_receiver.recv().unwrap() {
+            // after an error, the remaining work is skipped until the producer has stopped
+            Work::LINES(..) if error.is_some() => (),
             Work::LINES(number, lines) => {
-                for line in lines {
-                    handle(
-                        &mut lines_encoder,
-                        labels_encoder.as_mut(),
-                        statement_counter.as_mut(),
-                        number,
-                        line,
-                        &regex,
+                products.rows = outputs.sqlite.as_ref().map(|_| Vec::new());
+                'blocks: for block in entity_blocks(&lines) {
+                    let statements: Vec<Statement> = block
+                        .iter()
+                        .map(|line| parse(number, line, &regex))
+                        .collect();
+                    maybe_add_property_entry(products.property_entries.as_mut(), &statements);
+                    if !filter.keep(&statements) {
+                        continue;
+                    }
+                    if let (Some(entities), Some(id)) =
+                        (products.entities.as_mut(), block_entity(&statements))
+                    {
+                        entities.insert(id.key());
+                    }
+                    for (line, statement) in block.iter().zip(&statements) {
+                        if !filter.keep_statement(*statement) {
+                            continue;
+                        }
+                        if let (Some(entities), Some(id)) =
+                            (products.entities.as_mut(), entity(statement.subject))
+                        {
+                            entities.insert(id.key());
+                        }
+                        if let Err(err) = handle(
+                            &mut lines_encoder,
+                            &outputs,
+                            &mut products,
+                            line,
+                            *statement,
+                        ) {
+                            running.store(false, Ordering::SeqCst);
+                            error = Some(err);
+                            break 'blocks;
+                        }
+                    }
+                    maybe_add_property_stats(products.property_stats.as_mut(), &statements);
+                    maybe_write_sitelinks(
+                        products.sitelinks.as_mut(),
+                        &outputs.sitelinks_wikis,
+                        &statements,
                     );
+                    maybe_write_identifiers(products.identifiers.as_mut(), &statements);
+                    maybe_write_coordinates(products.coordinates.as_mut(), &statements);
+                    maybe_write_features(products.coordinates_geojson.as_mut(), &statements);
+                    maybe_write_dates(products.dates.as_mut(), &statements);
+                    maybe_write_quantities(products.quantities.as_mut(), &statements);
+                    if let Some(index) = outputs.index.as_ref() {
+                        index.add(&statements);
+                    }
+                }
+                if let (Some(sqlite), Some(rows)) = (outputs.sqlite.as_ref(), products.rows.take())
+                {
+                    sqlite.send(rows).unwrap();
                 }
+                maybe_send_counts(
+                    products.in_degree.as_mut(),
+                    outputs.in_degree.as_ref(),
+                    SEND_LEN,
+                );
+                maybe_send_counts(
+                    products.in_degree_by_property.as_mut(),
+                    outputs.in_degree_by_property.as_ref(),
+                    SEND_LEN,
+                );
                 lines_encoder.flush().unwrap();
-                if let Some(labels_encoder) = labels_encoder.as_mut() {
-                    labels_encoder.flush().unwrap()
+                for encoder in [
+                    products.labels.as_mut(),
+                    products.sitelinks.as_mut(),
+                    products.identifiers.as_mut(),
+                    products.coordinates.as_mut(),
+                    products.coordinates_geojson.as_mut(),
+                    products.dates.as_mut(),
+                    products.quantities.as_mut(),
+                    products.quarantine.as_mut(),
+                ]
+                .into_iter()
+                .flatten()
+                {
+                    encoder.flush().unwrap()
                 }
             }
             Work::DONE => {
                 eprintln!("# stopping thread {}", name);
                 lines_encoder.try_finish().unwrap();
-                if let Some(labels_encoder) = labels_encoder.as_mut() {
-                    labels_encoder.try_finish().unwrap()
+                for encoder in [
+                    products.labels.as_mut(),
+                    products.sitelinks.as_mut(),
+                    products.identifiers.as_mut(),
+                    products.coordinates.as_mut(),
+                    products.coordinates_geojson.as_mut(),
+                    products.dates.as_mut(),
+                    products.quantities.as_mut(),
+                    products.quarantine.as_mut(),
+                ]
+                .into_iter()
+                .flatten()
+                {
+                    encoder.try_finish().unwrap()
                 }
+                maybe_send_counts(products.in_degree.as_mut(), outputs.in_degree.as_ref(), 0);
+                maybe_send_counts(
+                    products.in_degree_by_property.as_mut(),
+                    outputs.in_degree_by_property.as_ref(),
+                    0,
+                );
 
                 result_sender
                     .send(WorkResult {
-                        statement_counts: statement_counter,
+                        statement_counts: products.statement_counter,
+                        label_runs: products.label_runs.map(LabelRuns::finish),
+                        property_stats: products.property_stats,
+                        property_entries: products.property_entries,
+                        entities: products.entities,
+                        sanitized: products.sanitized,
+                        error,
                     })
                     .unwrap();
 
@@ -331,75 +858,158 @@ fn consume(
 
 fn handle<T: Write, U: Write>(
     lines_writer: &mut T,
-    labels_writer: Option<&mut U>,
-    statement_counter: Option<&mut HashMap<String, u64>>,
-    number: u64,
-    line: String,
-    regex: &Regex,
-) -> Option<()> {
-    let statement = parse(number, &line, regex);
-    maybe_write_line(lines_writer, &line, statement);
-    let id = entity(statement.subject)?;
-    maybe_count_statement(statement_counter, id, statement);
-    maybe_write_label(labels_writer, id, statement);
-    None
+    outputs: &Outputs,
+    products: &mut Products<U>,
+    line: &str,
+    statement: Statement,
+) -> Result<(), String> {
+    // statements dropped by the rules refer to nothing in the output
+    if is_acceptable(statement) {
+        maybe_write_line(lines_writer, outputs, products, line, statement)?;
+        maybe_count_in_degree(
+            products.in_degree.as_mut(),
+            products.in_degree_by_property.as_mut(),
+            statement,
+        );
+    }
+    if let Some(id) = entity(statement.subject) {
+        maybe_count_statement(products.statement_counter.as_mut(), id, statement);
+        maybe_write_label(products.labels.as_mut(), id, statement);
+        maybe_add_row(products.rows.as_mut(), id, statement);
+        maybe_add_label_entry(products.label_runs.as_mut(), id, statement);
+    }
+    Ok(())
 }
 
-fn maybe_write_line<T: Write>(lines_writer: &mut T, line: &str, statement: Statement) {
-    if !is_acceptable(statement) {
-        return;
-    }
+fn maybe_write_line<T: Write, U: Write>(
+    lines_writer: &mut T,
+    outputs: &Outputs,
+    products: &mut Products<U>,
+    line: &str,
+    statement: Statement,
+) -> Result<(), String> {
+    let rewritten = match outputs.rewrites.as_ref() {
+        Some(rewrites) => rewrites.rewrite(statement),
+        None => None,
+    };
+    let (line, statement) = match rewritten.as_deref() {
+        Some(rewritten) => (rewritten, parse(0, rewritten, &RE)),
+        None => (line, statement),
+    };
 
-    lines_writer.write_all(line.as_bytes()).unwrap();
+    let relanged = (outputs.base_langs || outputs.normalize_langs)
+        .then(|| rewrite_lang(statement, outputs.base_langs, outputs.normalize_langs))
+        .flatten();
+    let (line, statement) = match relanged.as_deref() {
+        Some(relanged) => (relanged, parse(0, relanged, &RE)),
+        None => (line, statement),
+    };
+
+    let (Some(sanitizer), Some(counts)) = (outputs.sanitizer, products.sanitized.as_mut()) else {
+        return match outputs.script.as_deref() {
+            Some(script) => script.write(lines_writer, line, statement),
+            None => {
+                lines_writer.write_all(line.as_bytes()).unwrap();
+                Ok(())
+            }
+        };
+    };
+
+    // the triples are sanitized last, so that those the script writes are checked, too
+    let scripted;
+    let lines = match outputs.script.as_deref() {
+        Some(script) => {
+            let mut buffer = Vec::new();
+            script.write(&mut buffer, line, statement)?;
+            scripted = String::from_utf8(buffer).unwrap();
+            scripted.as_str()
+        }
+        None => line,
+    };
+    for line in lines.split_inclusive('\n') {
+        let statement = parse(0, line, &RE);
+        match sanitizer.sanitize(products.quarantine.as_mut(), counts, line, statement) {
+            Sanitized::Valid => lines_writer.write_all(line.as_bytes()).unwrap(),
+            Sanitized::Repaired(repaired) => lines_writer.write_all(repaired.as_bytes()).unwrap(),
+            Sanitized::Rejected => {}
+        }
+    }
+    Ok(())
 }
 
 fn maybe_write_label<T: Write>(
     labels_writer: Option<&mut T>,
-    id: &str,
+    id: EntityId,
     statement: Statement,
 ) -> Option<()> {
     let labels_writer = labels_writer?;
     let label = label(statement)?;
     labels_writer
-        .write_fmt(format_args!("{} {}\n", id, label))
+        .write_fmt(format_args!("{} {} {}\n", id.short(), id.kind, label))
         .unwrap();
     None
 }
 
 fn maybe_count_statement(
-    statement_counter: Option<&mut HashMap<String, u64>>,
-    id: &str,
+    statement_counter: Option<&mut Counts>,
+    id: EntityId,
     statement: Statement,
 ) -> Option<()> {
     let statement_counter = statement_counter?;
     direct_property(statement.predicate)?;
-    *statement_counter.entry(id.to_string()).or_insert(0) += 1;
+    statement_counter.increment(id.key());
     None
 }
 
-fn is_acceptable(statement: Statement) -> bool {
-    if PROPERTIES.contains(statement.predicate)
-        || IDENTIFIER_PROPERTIES.contains(statement.predicate)
-    {
-        return false;
+fn maybe_count_in_degree(
+    in_degree: Option<&mut Counts>,
+    in_degree_by_property: Option<&mut Counts<(u64, u64)>>,
+    statement: Statement,
+) -> Option<()> {
+    if in_degree.is_none() && in_degree_by_property.is_none() {
+        return None;
     }
-    match statement.subject {
-        Subject::Blank(_) => return false,
-        Subject::IRI(iri) if ignored_subject(iri) => return false,
-        _ => (),
+    let Object::IRI(iri) = statement.object else {
+        return None;
+    };
+    let id = EntityId::from_iri(iri)?.key();
+    if let Some(in_degree) = in_degree {
+        in_degree.increment(id);
     }
+    let property = direct_property(statement.predicate)?;
+    let property: u64 = property.strip_prefix('P')?.parse().ok()?;
+    in_degree_by_property?.increment((id, property));
+    None
+}
+
+fn is_acceptable(statement: Statement) -> bool {
+    rules().decide(statement) == Action::Keep
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
+pub enum Term {
+    Label,
+    Alias,
+    Description,
+}
+
+static LABEL_IRI: &str = "http://www.w3.org/2000/01/rdf-schema#label";
+static ALIAS_IRI: &str = "http://www.w3.org/2004/02/skos/core#altLabel";
+static DESCRIPTION_IRI: &str = "http://schema.org/description";
+
+/// Returns the kind of term and the language of a label, alias or description
+/// in one of the languages.
+fn term<'a>(statement: Statement<'a>) -> Option<(Term, &'a str)> {
+    let term = match statement.predicate {
+        p if p == LABEL_IRI => Term::Label,
+        p if p == ALIAS_IRI => Term::Alias,
+        p if p == DESCRIPTION_IRI => Term::Description,
+        _ => return None,
+    };
     match statement.object {
-        Object::Blank(_) => return false,
-        Object::Literal(_, Extra::Lang(lang)) if !LANGUAGES.contains(lang) => return false,
-        // non-Earth geo coordinates are not supported by some triple stores
-        Object::Literal(
-            literal,
-            Extra::Type("http://www.opengis.net/ont/geosparql#wktLiteral"),
-        ) if literal.starts_with('<') => return false,
-        _ => (),
+        Object::Literal(_, Extra::Lang(lang)) if languages().contains(lang) => Some((term, lang)),
+        _ => None,
     }
-
-    true
 }
 
 fn label(statement: Statement) -> Option<String> {
@@ -408,7 +1018,7 @@ fn label(statement: Statement) -> Option<String> {
     }
 
     if let Object::Literal(label, Extra::Lang(lang)) = statement.object {
-        if !LANGUAGES.contains(lang) {
+        if !languages().contains(lang) {
             return None;
         }
 
@@ -417,11 +1027,9 @@ fn label(statement: Statement) -> Option<String> {
 
     None
 }
-static ENTITY_IRI_PREFIX: &str = "http://www.wikidata.org/entity/Q";
-
-fn entity(subject: Subject) -> Option<&str> {
+fn entity(subject: Subject) -> Option<EntityId> {
     if let Subject::IRI(iri) = subject {
-        iri.strip_prefix(ENTITY_IRI_PREFIX)
+        EntityId::from_iri(iri)
     } else {
         None
     }
@@ -433,7 +1041,34 @@ fn direct_property(predicate: &str) -> Option<&str> {
     predicate.strip_prefix(DIRECT_PROPERTY_IRI_PREFIX)
 }
 
+/// Parses a range of values of a property, e.g. `P569:1800..`, into the property
+/// and the optional bounds, which are parsed with `parse_bound`.
+fn parse_property_range<T, F>(
+    s: &str,
+    parse_bound: F,
+) -> Result<(String, Option<T>, Option<T>), String>
+where
+    F: Fn(&str) -> Result<T, String>,
+{
+    let (property, range) = s
+        .split_once(':')
+        .ok_or_else(|| format!("expected <property>:<from>..<to>, got {}", s))?;
+    let property = EntityId::parse(property)
+        .filter(|id| id.kind == EntityKind::Property)
+        .ok_or_else(|| format!("invalid property: {}", property))?;
+    let (from, to) = range
+        .split_once("..")
+        .ok_or_else(|| format!("expected <from>..<to>, got {}", range))?;
+    let bound = |bound: &str| (!bound.is_empty()).then(|| parse_bound(bound)).transpose();
+    Ok((property.to_string(), bound(from)?, bound(to)?))
+}
+
 pub fn unescape(s: &str) -> String {
+    try_unescape(s).unwrap_or_else(|err| panic!("{}", err))
+}
+
+/// Unescapes an N-Triples literal, or returns an error for an invalid escape sequence.
+pub fn try_unescape(s: &str) -> Result<String, String> {
     let mut chars = s.chars().enumerate();
     let mut res = String::with_capacity(s.len());
 
@@ -441,7 +1076,7 @@ pub fn unescape(s: &str) -> String {
         if c == '\\' {
             match chars.next() {
                 None => {
-                    panic!("invalid escape at {} in {}", idx, s);
+                    return Err(format!("invalid escape at {} in {}", idx, s));
                 }
                 Some((idx, c2)) => {
                     res.push(match c2 {
@@ -450,22 +1085,18 @@ pub fn unescape(s: &str) -> String {
                         'n' => '\n',
                         'r' => '\r',
                         'f' => '\u{0C}',
+                        '"' => '"',
+                        '\'' => '\'',
                         '\\' => '\\',
 
-                        'u' => match parse_unicode(&mut chars, 4) {
-                            Ok(c3) => c3,
-                            Err(err) => {
-                                panic!("invalid escape {}{} at {} in {}: {}", c, c2, idx, s, err);
-                            }
-                        },
-                        'U' => match parse_unicode(&mut chars, 8) {
-                            Ok(c3) => c3,
-                            Err(err) => {
-                                panic!("invalid escape {}{} at {} in {}: {}", c, c2, idx, s, err);
-                            }
-                        },
+                        'u' => parse_unicode(&mut chars, 4).map_err(|err| {
+                            format!("invalid escape {}{} at {} in {}: {}", c, c2, idx, s, err)
+                        })?,
+                        'U' => parse_unicode(&mut chars, 8).map_err(|err| {
+                            format!("invalid escape {}{} at {} in {}: {}", c, c2, idx, s, err)
+                        })?,
                         _ => {
-                            panic!("invalid escape {}{} at {} in {}", c, c2, idx, s);
+                            return Err(format!("invalid escape {}{} at {} in {}", c, c2, idx, s));
                         }
                     });
                     continue;
@@ -476,6 +1107,48 @@ pub fn unescape(s: &str) -> String {
         res.push(c);
     }
 
+    Ok(res)
+}
+
+/// Returns whether the character is not allowed in an N-Triples IRI, e.g. a space or `>`.
+pub fn is_invalid_iri_char(c: char) -> bool {
+    c <= ' ' || "<>\"{}|^`\\".contains(c)
+}
+
+/// Percent-encodes the characters which are not allowed in an N-Triples IRI.
+pub fn encode_iri(iri: &str) -> Cow<'_, str> {
+    if !iri.contains(is_invalid_iri_char) {
+        return Cow::Borrowed(iri);
+    }
+    let mut encoded = String::with_capacity(iri.len() + 8);
+    for c in iri.chars() {
+        if is_invalid_iri_char(c) {
+            encoded.push_str(&format!("%{:02X}", c as u32));
+        } else {
+            encoded.push(c);
+        }
+    }
+    Cow::Owned(encoded)
+}
+
+/// Escapes a string for an N-Triples literal, in the canonical form of RDF 1.2:
+/// quotes, backslashes, backspaces, tabs, line breaks and form feeds with a backslash
+/// (e.g. `\t`), and the other control characters up to U+001F and U+007F as `\uXXXX`.
+pub fn escape(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            '\n' => res.push_str("\\n"),
+            '\r' => res.push_str("\\r"),
+            '\t' => res.push_str("\\t"),
+            '\u{08}' => res.push_str("\\b"),
+            '\u{0C}' => res.push_str("\\f"),
+            '\u{00}'..='\u{1F}' | '\u{7F}' => res.push_str(&format!("\\u{:04X}", c as u32)),
+            c => res.push(c),
+        }
+    }
     res
 }
 
@@ -492,10 +1165,55 @@ where
         })
 }
 
+fn write_counts<I, T, F>(path: &str, counts: I, mut write: F)
+where
+    I: Iterator<Item = T>,
+    F: FnMut(&mut BzEncoder<BufWriter<File>>, T) -> std::io::Result<()>,
+{
+    let file = File::create(path).unwrap_or_else(|_| panic!("unable to create file: {}", path));
+    let mut encoder = BzEncoder::new(BufWriter::new(file), Compression::best());
+    for count in counts {
+        write(&mut encoder, count).unwrap();
+    }
+    encoder.try_finish().unwrap();
+}
+
+fn write_statement_counts(
+    statement_counts: Vec<(u64, u64)>,
+    order: Order,
+    rank: bool,
+    top: Option<usize>,
+) {
+    let ranking = rank.then(|| Ranking::new(&statement_counts));
+    let statement_counts = counts::select(statement_counts, order, top);
+
+    write_counts(
+        "statement_counts.bz2",
+        statement_counts.into_iter(),
+        |encoder, (key, count)| {
+            let id = EntityId::from_key(key);
+            match ranking.as_ref() {
+                Some(ranking) => encoder.write_fmt(format_args!(
+                    "{} {} {} {} {:.4}\n",
+                    id.short(),
+                    id.kind,
+                    count,
+                    ranking.rank(count),
+                    ranking.percentile(count)
+                )),
+                None => encoder.write_fmt(format_args!("{} {} {}\n", id.short(), id.kind, count)),
+            }
+        },
+    );
+}
+
 fn main() {
     let opts: Opts = Opts::parse();
-    let labels = opts.labels;
-    let statement_counts = opts.statement_counts;
+    match opts.command {
+        Some(Command::Search(search_opts)) => return search::search(search_opts),
+        Some(Command::Serve(serve_opts)) => return serve::serve(serve_opts),
+        None => (),
+    }
 
     let running = Arc::new(AtomicBool::new(true));
     let r = running.clone();
@@ -513,18 +1231,129 @@ fn main() {
     let (work_sender, work_receiver) = bounded::<Work>(0);
     let (result_sender, result_receiver) = unbounded();
 
+    if !opts.langs.is_empty() {
+        CUSTOM_LANGUAGES
+            .set(LanguageSet::parse(opts.langs.iter().map(String::as_str)))
+            .unwrap();
+    }
+
+    let index = opts
+        .index
+        .as_ref()
+        .map(|path| Arc::new(SearchIndex::create(path)));
+
+    let sqlite = opts.sqlite.as_deref().map(SqliteWriter::spawn);
+    let in_degree_merger = opts.in_degree.then(CountsMerger::spawn);
+    let in_degree_by_property_merger = opts.in_degree_by_property.then(CountsMerger::spawn);
+
+    let outputs = Outputs {
+        labels: opts.labels,
+        sitelinks: opts.sitelinks,
+        sitelinks_wikis: Arc::new(opts.sitelinks_wikis.iter().cloned().collect()),
+        identifiers: opts.identifiers,
+        coordinates: opts.coordinates,
+        coordinates_geojson: opts.coordinates_geojson,
+        dates: opts.dates,
+        quantities: opts.quantities,
+        // the database includes the statement counts
+        statement_counts: opts.statement_counts || sqlite.is_some(),
+        label_store: opts.label_store.clone(),
+        property_stats: opts.property_stats,
+        property_catalog: opts.property_catalog,
+        in_degree: in_degree_merger.as_ref().map(CountsMerger::sender),
+        in_degree_by_property: in_degree_by_property_merger
+            .as_ref()
+            .map(CountsMerger::sender),
+        entities: opts.dangling.is_some(),
+        index: index.clone(),
+        rewrites: opts.rewrite.as_ref().map(|path| {
+            let source = std::fs::read_to_string(path)
+                .unwrap_or_else(|_| panic!("can't open file: {}", path));
+            Arc::new(
+                Rewrites::parse(&source)
+                    .unwrap_or_else(|err| panic!("invalid rewrite rules {}: {}", path, err)),
+            )
+        }),
+        script: opts.script.as_ref().map(|path| {
+            let source = std::fs::read_to_string(path)
+                .unwrap_or_else(|_| panic!("can't open file: {}", path));
+            Arc::new(
+                Script::compile(&source)
+                    .unwrap_or_else(|err| panic!("invalid script {}: {}", path, err)),
+            )
+        }),
+        sanitizer: opts
+            .sanitize
+            .map(|store| Sanitizer::new(store, opts.sanitize_action)),
+        normalize_langs: opts.normalize_langs,
+        base_langs: opts.base_langs,
+        sqlite: sqlite.as_ref().map(|sqlite| sqlite.sender()),
+    };
+
+    // the dangling references are found in the outputs, after the IRIs have been rewritten
+    let entity_prefix = match (opts.dangling, outputs.rewrites.as_ref()) {
+        (Some(_), Some(rewrites)) => rewrites
+            .entity_prefix()
+            .unwrap_or_else(|err| panic!("unable to check dangling references: {}", err)),
+        _ => ENTITY_IRI_PREFIX.to_string(),
+    };
+
+    let identifier_properties_paths = match opts.identifier_properties_dump.as_ref() {
+        Some(path) => Some(vec![path.clone()]),
+        None => opts
+            .derive_identifier_properties
+            .then(|| opts.paths.clone()),
+    };
+    if let Some(paths) = identifier_properties_paths {
+        let properties = identifiers::read_identifier_properties(&paths);
+        eprintln!("# identifier properties: {}", properties.len() / 2);
+        DUMP_IDENTIFIER_PROPERTIES.set(properties).unwrap();
+    }
+
+    if let Some(path) = opts.rules.as_ref() {
+        let source =
+            std::fs::read_to_string(path).unwrap_or_else(|_| panic!("can't open file: {}", path));
+        let rules =
+            Rules::parse(&source).unwrap_or_else(|err| panic!("invalid rules {}: {}", path, err));
+        CUSTOM_RULES.set(rules).unwrap();
+    }
+
+    let filter = Arc::new(EntityFilter {
+        kinds: opts.entity_kinds.iter().copied().collect(),
+        sitelink_wikis: opts.require_sitelinks.iter().cloned().collect(),
+        min_sitelinks: opts.min_sitelinks,
+        classes: (!opts.classes.is_empty())
+            .then(|| classes::read_classes(&opts.paths, &opts.classes, opts.subclasses)),
+        entities: (!opts.seeds.is_empty())
+            .then(|| subgraph::read_subgraph(&opts.paths, &opts.seeds, opts.hops)),
+        area: opts.bbox.clone().or_else(|| {
+            opts.polygon.as_ref().map(|path| {
+                let source = std::fs::read_to_string(path)
+                    .unwrap_or_else(|_| panic!("can't open file: {}", path));
+                Area::parse_polygon(&source)
+                    .unwrap_or_else(|err| panic!("invalid polygon {}: {}", path, err))
+            })
+        }),
+        date_ranges: opts.date_range.clone(),
+        quantity_ranges: opts.quantity_range.clone(),
+    });
+
     let mut threads = Vec::new();
     let thread_count = opts.threads.unwrap_or_else(|| num_cpus::get() * 2);
     for id in 1..=thread_count {
         let work_receiver = work_receiver.clone();
         let result_sender = result_sender.clone();
+        let outputs = outputs.clone();
+        let filter = filter.clone();
+        let running = running.clone();
         threads.push(thread::spawn(move || {
             consume(
                 id.to_string(),
                 work_receiver,
                 result_sender,
-                labels,
-                statement_counts,
+                outputs,
+                filter,
+                running,
             )
         }));
     }
@@ -550,14 +1379,38 @@ fn main() {
         work_sender.send(Work::DONE).unwrap();
     }
 
-    let mut statement_counter = HashMap::new();
+    drop(outputs);
+
+    let mut statement_counter = Counts::default();
+    let mut label_runs = Vec::new();
+    let mut property_statistics = PropertyStatistics::new();
+    let mut property_entries = Vec::new();
+    let mut entities = RoaringTreemap::new();
+    let mut sanitized = Counts::default();
 
     let mut result_count = 0;
     for result in result_receiver.iter() {
         if let Some(statement_counts) = result.statement_counts {
-            for (id, count) in statement_counts.iter() {
-                *statement_counter.entry(id.to_string()).or_insert(0) += count;
-            }
+            statement_counter.merge(statement_counts);
+        }
+        if let Some(runs) = result.label_runs {
+            label_runs.extend(runs);
+        }
+        if let Some(statistics) = result.property_stats {
+            merge_property_statistics(&mut property_statistics, statistics);
+        }
+        if let Some(entries) = result.property_entries {
+            property_entries.extend(entries);
+        }
+        if let Some(result_entities) = result.entities {
+            entities |= result_entities;
+        }
+        if let Some(counts) = result.sanitized {
+            sanitized.merge(counts);
+        }
+        if let Some(err) = result.error {
+            eprintln!("# error: {}", err);
+            exit_code = 1;
         }
 
         result_count += 1;
@@ -566,17 +1419,111 @@ fn main() {
         }
     }
 
-    if statement_counts {
-        eprintln!("# entities: {}", statement_counter.len());
-        let path = "statement_counts.bz2";
-        let file = File::create(path).unwrap_or_else(|_| panic!("unable to create file: {}", path));
-        let mut encoder = BzEncoder::new(BufWriter::new(file), Compression::best());
-        for (id, count) in statement_counter.iter() {
-            encoder
-                .write_fmt(format_args!("{} {}\n", id, count))
-                .unwrap();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    if let Some(dangling) = opts.dangling {
+        eprintln!("# included entities: {}", entities.len());
+        let names: Vec<String> = (1..=thread_count).map(|id| id.to_string()).collect();
+        integrity::check_references(&names, &entity_prefix, &entities, dangling);
+    }
+
+    if let Some(index) = index {
+        eprintln!("# committing index");
+        Arc::try_unwrap(index)
+            .unwrap_or_else(|_| panic!("index still in use"))
+            .commit();
+    }
+
+    let statement_counts = statement_counter.into_sorted();
+
+    if opts.property_stats {
+        eprintln!("# properties: {}", property_statistics.len());
+        write_property_statistics("property_stats.bz2", property_statistics);
+    }
+
+    if opts.property_catalog {
+        eprintln!("# catalog properties: {}", property_entries.len());
+        write_property_catalog(
+            "property_catalog.json",
+            "property_catalog.csv",
+            property_entries,
+        );
+    }
+
+    if let Some(merger) = in_degree_merger {
+        let in_degree = merger.finish().into_sorted();
+        eprintln!("# referenced entities: {}", in_degree.len());
+        write_counts(
+            "in_degree.bz2",
+            in_degree.into_iter(),
+            |encoder, (key, count)| {
+                let id = EntityId::from_key(key);
+                encoder.write_fmt(format_args!("{} {} {}\n", id.short(), id.kind, count))
+            },
+        );
+    }
+
+    if let Some(merger) = in_degree_by_property_merger {
+        write_counts(
+            "in_degree_by_property.bz2",
+            merger.finish().into_sorted().into_iter(),
+            |encoder, ((key, property), count)| {
+                let id = EntityId::from_key(key);
+                encoder.write_fmt(format_args!(
+                    "{} {} P{} {}\n",
+                    id.short(),
+                    id.kind,
+                    property,
+                    count
+                ))
+            },
+        );
+    }
+
+    if opts.sanitize.is_some() {
+        let sanitized = sanitized.into_sorted();
+        for ((reason, outcome), count) in &sanitized {
+            eprintln!("# sanitized: {} {} {}", reason, outcome, count);
+        }
+        write_counts(
+            "sanitized.bz2",
+            sanitized.into_iter(),
+            |encoder, ((reason, outcome), count)| {
+                encoder.write_fmt(format_args!("{} {} {}\n", reason, outcome, count))
+            },
+        );
+    }
+
+    if let Some(path) = opts.label_store {
+        let count = write_label_store(&path, &label_runs);
+        eprintln!("# labels: {}", count);
+    }
+
+    if let Some(sqlite) = sqlite {
+        let sender = sqlite.sender();
+        let mut rows = Vec::new();
+        for (id, count) in statement_counts.iter() {
+            rows.push(statement_count_row(*id, *count));
+            if rows.len() == BATCH_ROWS {
+                sender.send(rows).unwrap();
+                rows = Vec::new();
+            }
         }
-        encoder.try_finish().unwrap();
+        sender.send(rows).unwrap();
+        drop(sender);
+        sqlite.finish();
+    }
+
+    if opts.statement_counts {
+        eprintln!("# entities: {}", statement_counts.len());
+        write_statement_counts(
+            statement_counts,
+            opts.statement_counts_order,
+            opts.statement_counts_rank,
+            opts.statement_counts_top,
+        );
     }
 
     let duration = start.elapsed();
@@ -589,11 +1536,18 @@ fn main() {
 mod tests {
 
     use super::*;
+    use entity::ENTITY_IRI_PREFIX;
     use pretty_assertions::assert_eq;
+    use proptest::prelude::*;
     use std::fs::read_to_string;
     use std::io::{self, Lines};
     use std::path::{Path, PathBuf};
 
+    /// Parses N-Triples lines, e.g. of an entity block.
+    pub fn statements(lines: &[&'static str]) -> Vec<Statement<'static>> {
+        lines.iter().map(|line| parse(1, line, &RE)).collect()
+    }
+
     #[test]
     fn test_literal_with_type() {
         let line = r#"<http://www.wikidata.org/entity/Q1644> <http://www.wikidata.org/prop/direct/P2043> "+1094.26"^^<http://www.w3.org/2001/XMLSchema#decimal> ."#;
@@ -662,10 +1616,90 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_escaped_quote() {
+        let line = r#"<http://www.wikidata.org/entity/Q8> <http://schema.org/name> "\"happiness\"\\"@en ."#;
+        let statement = parse(1, line, &RE);
+        assert_eq!(
+            statement.object,
+            Object::Literal(r#"\"happiness\"\\"#, Extra::Lang("en"))
+        );
+        assert_eq!(statement.to_string(), line);
+    }
+
+    #[test]
+    fn test_canonical_escaping() {
+        let line = r#"_:b1 <http://schema.org/name> "a\"b\u0009c\u0001\U0001F600"^^<http://www.w3.org/2001/XMLSchema#string> ."#;
+        assert_eq!(
+            parse(1, line, &RE).to_string(),
+            r#"_:b1 <http://schema.org/name> "a\"b\tc\u0001😀"^^<http://www.w3.org/2001/XMLSchema#string> ."#
+        );
+
+        assert_eq!(escape("\t"), r"\t");
+        assert_eq!(escape("\u{08}"), r"\b");
+        assert_eq!(escape("\u{0C}"), r"\f");
+        assert_eq!(escape("\u{01}"), r"\u0001");
+        assert_eq!(escape("\u{1F}\u{7F}"), r"\u001F\u007F");
+        assert_eq!(escape("\u{80}é"), "\u{80}é");
+
+        // invalid escapes and IRIs are written as valid N-Triples
+        let statement = Statement {
+            subject: Subject::IRI("http://example.com/a b"),
+            predicate: "http://example.com/p>",
+            object: Object::Literal(r"stray \ backslash", Extra::None),
+        };
+        assert_eq!(
+            statement.to_string(),
+            r#"<http://example.com/a%20b> <http://example.com/p%3E> "stray \\ backslash" ."#
+        );
+        let object = Object::IRI("http://example.com/\"q\"");
+        assert_eq!(object.to_string(), "<http://example.com/%22q%22>");
+    }
+
+    fn subject_strategy() -> impl Strategy<Value = (bool, String)> {
+        (any::<bool>(), "[a-zA-Z0-9:/.#_-]{1,30}")
+    }
+
+    proptest! {
+        #[test]
+        fn test_round_trip(
+            (blank_subject, subject) in subject_strategy(),
+            predicate in "[a-zA-Z0-9:/.#_-]{1,30}",
+            literal in any::<String>(),
+            lang in "[a-z]{2,3}(-[A-Z]{2})?",
+            extra in 0..3,
+        ) {
+            let escaped = escape(&literal);
+            let statement = Statement {
+                subject: if blank_subject {
+                    Subject::Blank(&subject)
+                } else {
+                    Subject::IRI(&subject)
+                },
+                predicate: &predicate,
+                object: Object::Literal(
+                    &escaped,
+                    match extra {
+                        0 => Extra::None,
+                        1 => Extra::Lang(&lang),
+                        _ => Extra::Type("http://www.w3.org/2001/XMLSchema#string"),
+                    },
+                ),
+            };
+            let line = statement.to_string();
+            let parsed = parse(1, &line, &RE);
+            prop_assert_eq!(parsed, statement);
+            prop_assert_eq!(parsed.to_string(), line.clone());
+            if let Object::Literal(parsed_literal, _) = parsed.object {
+                prop_assert_eq!(unescape(parsed_literal), literal);
+            }
+        }
+    }
+
     #[test]
     fn test_statement_count() {
-        let a = format!("{}a", ENTITY_IRI_PREFIX);
-        let b = format!("{}b", ENTITY_IRI_PREFIX);
+        let a = format!("{}Q1", ENTITY_IRI_PREFIX);
+        let b = format!("{}Q2", ENTITY_IRI_PREFIX);
 
         let first_predicate = format!("{}first", DIRECT_PROPERTY_IRI_PREFIX);
         let second_predicate = "second";
@@ -686,13 +1720,35 @@ mod tests {
             predicate: third_predicate.as_str(),
             object: Object::IRI(""),
         };
-        let mut counter = HashMap::new();
-        maybe_count_statement(Some(&mut counter), "a", first);
-        maybe_count_statement(Some(&mut counter), "b", second);
-        maybe_count_statement(Some(&mut counter), "a", third);
-        assert_eq!(counter.len(), 1);
-        assert_eq!(counter.get("a"), Some(&2));
-        assert_eq!(counter.get("b"), None);
+        let mut counter = Counts::default();
+        maybe_count_statement(Some(&mut counter), EntityId::parse("Q1").unwrap(), first);
+        maybe_count_statement(Some(&mut counter), EntityId::parse("Q2").unwrap(), second);
+        maybe_count_statement(Some(&mut counter), EntityId::parse("Q1").unwrap(), third);
+        assert_eq!(counter.into_sorted(), vec![(1, 2)]);
+    }
+
+    #[test]
+    fn test_in_degree() {
+        let mut in_degree = Counts::default();
+        let mut in_degree_by_property = Counts::default();
+        for line in [
+            r#"<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q5> ."#,
+            r#"<http://www.wikidata.org/entity/Q2> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q5> ."#,
+            r#"<http://www.wikidata.org/entity/Q2> <http://www.wikidata.org/prop/direct/P279> <http://www.wikidata.org/entity/Q5> ."#,
+            r#"<http://www.wikidata.org/entity/Q2> <http://www.wikidata.org/prop/direct/P279> <http://www.wikidata.org/entity/Q1> ."#,
+            r#"<https://en.wikipedia.org/wiki/Human> <http://schema.org/about> <http://www.wikidata.org/entity/Q5> ."#,
+        ] {
+            maybe_count_in_degree(
+                Some(&mut in_degree),
+                Some(&mut in_degree_by_property),
+                parse(1, line, &RE),
+            );
+        }
+        assert_eq!(in_degree.into_sorted(), vec![(1, 1), (5, 4)]);
+        assert_eq!(
+            in_degree_by_property.into_sorted(),
+            vec![((1, 279), 1), ((5, 31), 2), ((5, 279), 1)]
+        );
     }
 
     #[test]
@@ -709,6 +1765,77 @@ mod tests {
         )));
     }
 
+    #[test]
+    fn test_identifier_properties_options() {
+        assert!(Opts::try_parse_from([
+            "wikidata-filter",
+            "--derive-identifier-properties",
+            "a.nt.bz2"
+        ])
+        .is_ok());
+        assert!(Opts::try_parse_from([
+            "wikidata-filter",
+            "--derive-identifier-properties",
+            "--identifier-properties-dump",
+            "properties.nt.bz2",
+            "a.nt.bz2"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_entity_blocks() {
+        let lines: Vec<String> = [
+            "<https://www.wikidata.org/wiki/Special:EntityData/Q1> <http://schema.org/about> <http://www.wikidata.org/entity/Q1> .",
+            "<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q2> .",
+            "<https://en.wikipedia.org/wiki/Universe> <http://schema.org/about> <http://www.wikidata.org/entity/Q1> .",
+            "<https://www.wikidata.org/wiki/Special:EntityData/Q2> <http://schema.org/about> <http://www.wikidata.org/entity/Q2> .",
+            "<http://www.wikidata.org/entity/Q2> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q3> .",
+        ]
+        .iter()
+        .map(|line| line.to_string())
+        .collect();
+
+        let blocks = entity_blocks(&lines);
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0], &lines[..3]);
+        assert_eq!(blocks[1], &lines[3..]);
+
+        let lines: Vec<String> = [
+            "<https://www.wikidata.org/wiki/Special:EntityData/L7> <http://schema.org/about> <http://www.wikidata.org/entity/L7> .",
+            "<http://www.wikidata.org/entity/L7> <http://www.w3.org/ns/lemon/ontolex#lexicalForm> <http://www.wikidata.org/entity/L7-F1> .",
+            "<http://www.wikidata.org/entity/L7-F1> <http://www.w3.org/ns/lemon/ontolex#representation> \"Hund\"@de .",
+            "<http://www.wikidata.org/entity/L7-S1> <http://www.w3.org/2004/02/skos/core#definition> \"dog\"@en .",
+            "<https://www.wikidata.org/wiki/Special:EntityData/L8> <http://schema.org/about> <http://www.wikidata.org/entity/L8> .",
+        ]
+        .iter()
+        .map(|line| line.to_string())
+        .collect();
+
+        let blocks = entity_blocks(&lines);
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0], &lines[..4]);
+        assert_eq!(blocks[1], &lines[4..]);
+    }
+
+    #[test]
+    fn test_label_kinds() {
+        let statements = statements(&[
+            r#"<http://www.wikidata.org/entity/L7-F1> <http://www.w3.org/2000/01/rdf-schema#label> "Hund"@en ."#,
+            r#"<http://www.wikidata.org/entity/L7-S1> <http://www.w3.org/2000/01/rdf-schema#label> "dog"@en ."#,
+            r#"<http://www.wikidata.org/entity/P31> <http://www.w3.org/2000/01/rdf-schema#label> "instance of"@en ."#,
+        ]);
+        let mut output = Vec::new();
+        for statement in statements {
+            let id = entity(statement.subject).unwrap();
+            maybe_write_label(Some(&mut output), id, statement);
+        }
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "7-F1 form Hund\n7-S1 sense dog\n31 property instance of\n"
+        );
+    }
+
     fn read_lines<P>(filename: P) -> io::Result<Lines<BufReader<File>>>
     where
         P: AsRef<Path>,
@@ -734,26 +1861,32 @@ mod tests {
         let labels_out_path = labels_out_path.as_os_str().to_str().unwrap();
 
         let mut lines_writer = Vec::new();
-        let mut labels_writer = Vec::new();
+        let mut products = Products {
+            labels: Some(Vec::new()),
+            ..Default::default()
+        };
 
         for (line, number) in read_lines(in_path).unwrap().zip(1u64..) {
             let mut line = line.unwrap();
             line.push('\n');
             handle(
                 &mut lines_writer,
-                Some(&mut labels_writer),
-                None,
-                number,
-                line,
-                &RE,
-            );
+                &Outputs::default(),
+                &mut products,
+                &line,
+                parse(number, &line, &RE),
+            )
+            .unwrap();
         }
 
         let lines_expected = read_to_string(lines_out_path).unwrap();
         assert_eq!(String::from_utf8(lines_writer).unwrap(), lines_expected);
 
         let labels_expected = read_to_string(labels_out_path).unwrap();
-        assert_eq!(String::from_utf8(labels_writer).unwrap(), labels_expected);
+        assert_eq!(
+            String::from_utf8(products.labels.unwrap()).unwrap(),
+            labels_expected
+        );
 
         Ok(())
     }
@@ -0,0 +1,151 @@
+use crate::entity::EntityKind;
+use crate::filter::block_entity;
+use crate::{
+    direct_property, identifier_properties, unescape, Extra, Object, Statement, LABEL_IRI,
+};
+use serde_json::json;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+static PROPERTY_TYPE_IRI: &str = "http://wikiba.se/ontology#propertyType";
+static WIKIBASE_ONTOLOGY_PREFIX: &str = "http://wikiba.se/ontology#";
+static FORMATTER_URL_PROPERTY: &str = "P1630";
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct PropertyEntry {
+    number: u64,
+    datatype: Option<String>,
+    label: Option<String>,
+    formatter_url: Option<String>,
+    identifier: bool,
+}
+
+/// Adds the entry of a property, if the entity block is the one of a property.
+/// The catalog includes all properties, so this is called before the entity filter.
+pub fn maybe_add_property_entry(
+    entries: Option<&mut Vec<PropertyEntry>>,
+    statements: &[Statement],
+) -> Option<()> {
+    let entries = entries?;
+    let property = block_entity(statements)?;
+    if property.kind != EntityKind::Property {
+        return None;
+    }
+    let mut entry = PropertyEntry {
+        number: property.number,
+        identifier: identifier_properties()
+            .contains(&format!("http://www.wikidata.org/prop/direct/{}", property)),
+        ..Default::default()
+    };
+    for statement in statements {
+        match statement.object {
+            Object::IRI(iri) if statement.predicate == PROPERTY_TYPE_IRI => {
+                entry.datatype = iri
+                    .strip_prefix(WIKIBASE_ONTOLOGY_PREFIX)
+                    .map(|datatype| datatype.to_string());
+            }
+            Object::Literal(url, _)
+                if entry.formatter_url.is_none()
+                    && direct_property(statement.predicate) == Some(FORMATTER_URL_PROPERTY) =>
+            {
+                entry.formatter_url = Some(unescape(url));
+            }
+            // the English label, no matter which languages are included
+            Object::Literal(label, Extra::Lang("en"))
+                if entry.label.is_none() && statement.predicate == LABEL_IRI =>
+            {
+                entry.label = Some(unescape(label));
+            }
+            _ => (),
+        }
+    }
+    entries.push(entry);
+    None
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Writes the catalog of all properties, ordered by property id, as JSON and CSV.
+pub fn write_property_catalog(json_path: &str, csv_path: &str, mut entries: Vec<PropertyEntry>) {
+    entries.sort_by_key(|entry| entry.number);
+
+    let json_file =
+        File::create(json_path).unwrap_or_else(|_| panic!("unable to create file: {}", json_path));
+    let json_entries: Vec<_> = entries
+        .iter()
+        .map(|entry| {
+            json!({
+                "id": format!("P{}", entry.number),
+                "datatype": entry.datatype,
+                "label": entry.label,
+                "formatterURL": entry.formatter_url,
+                "identifier": entry.identifier,
+            })
+        })
+        .collect();
+    let mut json_writer = BufWriter::new(json_file);
+    serde_json::to_writer_pretty(&mut json_writer, &json_entries).unwrap();
+    json_writer.write_all(b"\n").unwrap();
+    json_writer.flush().unwrap();
+
+    let csv_file =
+        File::create(csv_path).unwrap_or_else(|_| panic!("unable to create file: {}", csv_path));
+    let mut csv_writer = BufWriter::new(csv_file);
+    csv_writer
+        .write_all(b"id,datatype,label,formatter_url,identifier\n")
+        .unwrap();
+    for entry in &entries {
+        csv_writer
+            .write_fmt(format_args!(
+                "P{},{},{},{},{}\n",
+                entry.number,
+                csv_field(entry.datatype.as_deref().unwrap_or_default()),
+                csv_field(entry.label.as_deref().unwrap_or_default()),
+                csv_field(entry.formatter_url.as_deref().unwrap_or_default()),
+                entry.identifier
+            ))
+            .unwrap();
+    }
+    csv_writer.flush().unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::tests::statements;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_property_entry() {
+        let statements = statements(&[
+            r#"<https://www.wikidata.org/wiki/Special:EntityData/P214> <http://schema.org/about> <http://www.wikidata.org/entity/P214> ."#,
+            r#"<http://www.wikidata.org/entity/P214> <http://wikiba.se/ontology#propertyType> <http://wikiba.se/ontology#ExternalId> ."#,
+            r#"<http://www.wikidata.org/entity/P214> <http://www.w3.org/2000/01/rdf-schema#label> "VIAF-Kennung"@de ."#,
+            r#"<http://www.wikidata.org/entity/P214> <http://www.w3.org/2000/01/rdf-schema#label> "VIAF ID"@en ."#,
+            r#"<http://www.wikidata.org/entity/P214> <http://www.w3.org/2004/02/skos/core#altLabel> "VIAF"@en ."#,
+            r#"<http://www.wikidata.org/entity/P214> <http://www.wikidata.org/prop/direct/P1630> "https://viaf.org/viaf/$1/" ."#,
+        ]);
+
+        let mut entries = Vec::new();
+        maybe_add_property_entry(Some(&mut entries), &statements);
+        maybe_add_property_entry(Some(&mut entries), &statements[..0]);
+        assert_eq!(
+            entries,
+            vec![PropertyEntry {
+                number: 214,
+                datatype: Some("ExternalId".to_string()),
+                label: Some("VIAF ID".to_string()),
+                formatter_url: Some("https://viaf.org/viaf/$1/".to_string()),
+                identifier: true,
+            }]
+        );
+        assert_eq!(csv_field("a, \"b\""), "\"a, \"\"b\"\"\"");
+    }
+}
@@ -0,0 +1,262 @@
+use crate::{direct_property, Extra, Object, Statement};
+use bzip2::write::BzEncoder;
+use bzip2::Compression;
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+const EXAMPLE_COUNT: usize = 3;
+const EXAMPLE_SEPARATOR: &str = " | ";
+
+/// The counts of the kinds of objects, e.g. IRIs or literals by language.
+/// Languages and datatypes are only copied when they first occur.
+#[derive(Default, Debug, PartialEq, Eq)]
+struct ObjectKinds {
+    iris: u64,
+    blanks: u64,
+    literals: u64,
+    langs: HashMap<String, u64>,
+    data_types: HashMap<String, u64>,
+}
+
+fn increment(counts: &mut HashMap<String, u64>, key: &str) {
+    match counts.get_mut(key) {
+        Some(count) => *count += 1,
+        None => {
+            counts.insert(key.to_string(), 1);
+        }
+    }
+}
+
+impl ObjectKinds {
+    fn add(&mut self, object: Object) {
+        match object {
+            Object::IRI(_) => self.iris += 1,
+            Object::Blank(_) => self.blanks += 1,
+            Object::Literal(_, Extra::None) => self.literals += 1,
+            Object::Literal(_, Extra::Lang(lang)) => increment(&mut self.langs, lang),
+            Object::Literal(_, Extra::Type(data_type)) => {
+                increment(&mut self.data_types, data_type)
+            }
+        }
+    }
+
+    fn merge(&mut self, other: ObjectKinds) {
+        self.iris += other.iris;
+        self.blanks += other.blanks;
+        self.literals += other.literals;
+        for (lang, count) in other.langs {
+            *self.langs.entry(lang).or_insert(0) += count;
+        }
+        for (data_type, count) in other.data_types {
+            *self.data_types.entry(data_type).or_insert(0) += count;
+        }
+    }
+
+    /// Returns the kinds with their counts, e.g. `iri`, `literal@en` or `literal^^<datatype>`,
+    /// the most frequent first.
+    fn counts(self) -> Vec<(String, u64)> {
+        let mut counts: Vec<_> = [
+            ("iri".to_string(), self.iris),
+            ("blank".to_string(), self.blanks),
+            ("literal".to_string(), self.literals),
+        ]
+        .into_iter()
+        .filter(|(_, count)| *count > 0)
+        .chain(
+            self.langs
+                .into_iter()
+                .map(|(lang, count)| (format!("literal@{}", lang), count)),
+        )
+        .chain(
+            self.data_types
+                .into_iter()
+                .map(|(data_type, count)| (format!("literal^^{}", data_type), count)),
+        )
+        .collect();
+        counts.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+        counts
+    }
+}
+
+#[derive(Default, Debug, PartialEq, Eq)]
+pub struct PropertyStats {
+    triples: u64,
+    subjects: u64,
+    kinds: ObjectKinds,
+    examples: Vec<String>,
+}
+
+impl PropertyStats {
+    fn merge(&mut self, other: PropertyStats) {
+        self.triples += other.triples;
+        self.subjects += other.subjects;
+        self.kinds.merge(other.kinds);
+        for example in other.examples {
+            self.add_example(example);
+        }
+    }
+
+    fn add_example(&mut self, example: String) {
+        if self.examples.len() < EXAMPLE_COUNT && !self.examples.contains(&example) {
+            self.examples.push(example);
+        }
+    }
+}
+
+/// Usage statistics of the direct properties, keyed by property id (e.g. `P31`)
+pub type PropertyStatistics = HashMap<String, PropertyStats>;
+
+fn object_text(object: Object) -> String {
+    match object {
+        Object::IRI(iri) => format!("<{}>", iri),
+        Object::Blank(blank) => format!("_:{}", blank),
+        Object::Literal(literal, Extra::None) => format!("\"{}\"", literal),
+        Object::Literal(literal, Extra::Lang(lang)) => format!("\"{}\"@{}", literal, lang),
+        Object::Literal(literal, Extra::Type(data_type)) => {
+            format!("\"{}\"^^<{}>", literal, data_type)
+        }
+    }
+}
+
+/// Adds the direct statements of an entity block.
+/// All statements of a subject are in one block, so its distinct subjects are counted per block.
+/// A lexeme block has several subjects, the lexeme and its forms and senses.
+pub fn maybe_add_property_stats(
+    statistics: Option<&mut PropertyStatistics>,
+    statements: &[Statement],
+) -> Option<()> {
+    let statistics = statistics?;
+    let mut subjects = HashSet::new();
+    for statement in statements {
+        let Some(property) = direct_property(statement.predicate) else {
+            continue;
+        };
+        let stats = statistics.entry(property.to_string()).or_default();
+        stats.triples += 1;
+        stats.kinds.add(statement.object);
+        if stats.examples.len() < EXAMPLE_COUNT {
+            stats.add_example(object_text(statement.object));
+        }
+        subjects.insert((property, statement.subject));
+    }
+    for (property, _) in subjects {
+        if let Some(stats) = statistics.get_mut(property) {
+            stats.subjects += 1;
+        }
+    }
+    None
+}
+
+pub fn merge_property_statistics(statistics: &mut PropertyStatistics, other: PropertyStatistics) {
+    for (property, stats) in other {
+        statistics.entry(property).or_default().merge(stats);
+    }
+}
+
+fn property_number(property: &str) -> u64 {
+    property
+        .strip_prefix('P')
+        .and_then(|number| number.parse().ok())
+        .unwrap_or(u64::MAX)
+}
+
+/// Writes one tab-separated row per property, ordered by property id:
+/// property, triples, distinct subjects, object kinds with counts, and example values,
+/// separated by ` | `.
+pub fn write_property_statistics(path: &str, statistics: PropertyStatistics) {
+    let mut statistics: Vec<_> = statistics.into_iter().collect();
+    statistics.sort_by(|(a, _), (b, _)| (property_number(a), a).cmp(&(property_number(b), b)));
+
+    let file = File::create(path).unwrap_or_else(|_| panic!("unable to create file: {}", path));
+    let mut encoder = BzEncoder::new(BufWriter::new(file), Compression::best());
+    for (property, stats) in statistics {
+        let kinds: Vec<_> = stats
+            .kinds
+            .counts()
+            .into_iter()
+            .map(|(kind, count)| format!("{}={}", kind, count))
+            .collect();
+        encoder
+            .write_fmt(format_args!(
+                "{}\t{}\t{}\t{}\t{}\n",
+                property,
+                stats.triples,
+                stats.subjects,
+                kinds.join(" "),
+                stats.examples.join(EXAMPLE_SEPARATOR)
+            ))
+            .unwrap();
+    }
+    encoder.try_finish().unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::tests::statements;
+    use pretty_assertions::assert_eq;
+    use std::io::Read;
+
+    #[test]
+    fn test_property_stats() {
+        let first = statements(&[
+            r#"<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q5> ."#,
+            r#"<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q6> ."#,
+            r#"<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P1476> "Title"@en ."#,
+            r#"<http://www.wikidata.org/entity/Q1> <http://schema.org/name> "One"@en ."#,
+        ]);
+        let second = statements(&[
+            r#"<http://www.wikidata.org/entity/Q2> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q5> ."#,
+        ]);
+        let lexeme = statements(&[
+            r#"<http://www.wikidata.org/entity/L7> <http://www.wikidata.org/prop/direct/P5185> <http://www.wikidata.org/entity/Q499327> ."#,
+            r#"<http://www.wikidata.org/entity/L7-F1> <http://www.wikidata.org/prop/direct/P898> "hʊnt" ."#,
+            r#"<http://www.wikidata.org/entity/L7-F2> <http://www.wikidata.org/prop/direct/P898> "ˈhʊndə" ."#,
+            r#"<http://www.wikidata.org/entity/L7-F2> <http://www.wikidata.org/prop/direct/P898> "ˈhʊndɐ" ."#,
+        ]);
+
+        let mut statistics = PropertyStatistics::new();
+        maybe_add_property_stats(Some(&mut statistics), &first);
+        let mut other = PropertyStatistics::new();
+        maybe_add_property_stats(Some(&mut other), &second);
+        maybe_add_property_stats(Some(&mut other), &lexeme);
+        merge_property_statistics(&mut statistics, other);
+
+        assert_eq!(statistics.len(), 4);
+        let p31 = statistics.remove("P31").unwrap();
+        assert_eq!((p31.triples, p31.subjects), (3, 2));
+        assert_eq!(
+            p31.examples,
+            vec![
+                "<http://www.wikidata.org/entity/Q5>".to_string(),
+                "<http://www.wikidata.org/entity/Q6>".to_string()
+            ]
+        );
+        assert_eq!(p31.kinds.counts(), vec![("iri".to_string(), 3)]);
+        let p898 = statistics.remove("P898").unwrap();
+        assert_eq!((p898.triples, p898.subjects), (3, 2));
+        assert_eq!(p898.kinds.counts(), vec![("literal".to_string(), 3)]);
+        assert_eq!(
+            statistics.remove("P1476").unwrap().kinds.counts(),
+            vec![("literal@en".to_string(), 1)]
+        );
+
+        let path = std::env::temp_dir().join("test_property_stats.bz2");
+        let path = path.to_str().unwrap();
+        let mut statistics = PropertyStatistics::new();
+        maybe_add_property_stats(Some(&mut statistics), &first);
+        write_property_statistics(path, statistics);
+        let mut written = String::new();
+        bzip2::read::BzDecoder::new(File::open(path).unwrap())
+            .read_to_string(&mut written)
+            .unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(
+            written,
+            "P31\t2\t1\tiri=2\t<http://www.wikidata.org/entity/Q5> | <http://www.wikidata.org/entity/Q6>\n\
+             P1476\t1\t1\tliteral@en=1\t\"Title\"@en\n"
+        );
+    }
+}
@@ -0,0 +1,121 @@
+use crate::entity::EntityId;
+use crate::{direct_property, entity, parse_property_range, Extra, Object, Statement};
+use bigdecimal::BigDecimal;
+use std::io::Write;
+use std::str::FromStr;
+
+static NUMERIC_DATA_TYPES: [&str; 3] = [
+    "http://www.w3.org/2001/XMLSchema#decimal",
+    "http://www.w3.org/2001/XMLSchema#integer",
+    "http://www.w3.org/2001/XMLSchema#double",
+];
+
+/// Returns the value of a direct statement with a numeric value, e.g. of the population (P1082).
+/// The values are parsed exactly, no matter how many digits they have.
+/// Truthy statements don't include the unit, e.g. metres for the elevation (P2044).
+fn quantity<'a>(statement: Statement<'a>) -> Option<(EntityId, &'a str, BigDecimal)> {
+    let Object::Literal(literal, Extra::Type(data_type)) = statement.object else {
+        return None;
+    };
+    if !NUMERIC_DATA_TYPES.contains(&data_type) {
+        return None;
+    }
+    let entity = entity(statement.subject)?;
+    let property = direct_property(statement.predicate)?;
+    Some((entity, property, BigDecimal::from_str(literal).ok()?))
+}
+
+/// Writes one tab-separated row per numeric value of an entity block: entity, property and value.
+pub fn maybe_write_quantities<T: Write>(
+    quantities_writer: Option<&mut T>,
+    statements: &[Statement],
+) -> Option<()> {
+    let quantities_writer = quantities_writer?;
+    for (entity, property, value) in statements.iter().filter_map(|s| quantity(*s)) {
+        quantities_writer
+            .write_fmt(format_args!(
+                "{}\t{}\t{}\n",
+                entity,
+                property,
+                value.to_plain_string()
+            ))
+            .unwrap();
+    }
+    None
+}
+
+/// A range of numeric values of a property, e.g. `P1082:1000000..` for a population
+/// of at least a million. Both bounds are optional and inclusive.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuantityRange {
+    property: String,
+    from: Option<BigDecimal>,
+    to: Option<BigDecimal>,
+}
+
+impl QuantityRange {
+    fn contains(&self, value: &BigDecimal) -> bool {
+        self.from.as_ref().is_none_or(|from| value >= from)
+            && self.to.as_ref().is_none_or(|to| value <= to)
+    }
+
+    /// Returns whether the entity has a value of the property within the range.
+    pub fn contains_entity(&self, statements: &[Statement]) -> bool {
+        statements
+            .iter()
+            .filter_map(|statement| quantity(*statement))
+            .any(|(_, property, value)| property == self.property && self.contains(&value))
+    }
+}
+
+impl FromStr for QuantityRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (property, from, to) = parse_property_range(s, |bound| {
+            BigDecimal::from_str(bound).map_err(|_| format!("invalid number: {}", bound))
+        })?;
+        Ok(QuantityRange { property, from, to })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::tests::statements;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_quantities() {
+        let statements = statements(&[
+            r#"<http://www.wikidata.org/entity/Q1644> <http://www.wikidata.org/prop/direct/P2043> "+1094.26"^^<http://www.w3.org/2001/XMLSchema#decimal> ."#,
+            r#"<http://www.wikidata.org/entity/Q1644> <http://www.wikidata.org/prop/direct/P1082> "+123456789012345678901234567890.000000000001"^^<http://www.w3.org/2001/XMLSchema#decimal> ."#,
+            r#"<http://www.wikidata.org/entity/Q1644> <http://www.wikidata.org/prop/direct/P2044> "-5"^^<http://www.w3.org/2001/XMLSchema#decimal> ."#,
+            r#"<http://www.wikidata.org/entity/Q1644> <http://www.wikidata.org/prop/direct/P373> "Ems" ."#,
+        ]);
+
+        let mut output = Vec::new();
+        maybe_write_quantities(Some(&mut output), &statements);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Q1644\tP2043\t1094.26\n\
+             Q1644\tP1082\t123456789012345678901234567890.000000000001\n\
+             Q1644\tP2044\t-5\n"
+        );
+
+        let range = |s: &str| s.parse::<QuantityRange>().unwrap();
+        assert!(range("P2043:1000..").contains_entity(&statements));
+        assert!(range("P2043:..1094.26").contains_entity(&statements));
+        assert!(!range("P2043:..1094.25").contains_entity(&statements));
+        assert!(range("P1082:123456789012345678901234567890..").contains_entity(&statements));
+        assert!(
+            !range("P1082:123456789012345678901234567890.000000000002..")
+                .contains_entity(&statements)
+        );
+        assert!(range("P2044:-10..0").contains_entity(&statements));
+        assert!(!range("P373:0..").contains_entity(&statements));
+        assert!("P2043:x..".parse::<QuantityRange>().is_err());
+        assert!("Q5:1..".parse::<QuantityRange>().is_err());
+    }
+}
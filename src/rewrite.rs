@@ -0,0 +1,360 @@
+use crate::entity::ENTITY_IRI_PREFIX;
+use crate::rules::expand;
+use crate::{is_invalid_iri_char, Object, Statement, Subject};
+use regex::Regex;
+
+// Rewrite rules change IRIs, one rule per line:
+//
+// <target> exact <iri> <replacement>
+// <target> prefix <prefix> <replacement prefix>
+// <target> regex <regex> <replacement, may refer to groups, e.g. $1>
+// <target> commons-upload
+//
+// The target is `subject`, `predicate`, `object`, or `iri` for all of them.
+// IRIs may be prefixed names, e.g. `wdt:P31`, or full IRIs, e.g. `http://example.com/` or
+// `<urn:example>`. The first matching rule for an IRI applies. A replacement which adds
+// characters which are not allowed in IRIs, e.g. a space, is an error. Such characters which
+// the IRI already has are percent-encoded when the triple is written.
+
+static COMMONS_FILE_PATH_PREFIX: &str = "http://commons.wikimedia.org/wiki/Special:FilePath/";
+static COMMONS_UPLOAD_PREFIX: &str = "https://upload.wikimedia.org/wikipedia/commons/";
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+enum Position {
+    Subject,
+    Predicate,
+    Object,
+}
+
+#[derive(Debug)]
+enum Kind {
+    Exact(String, String),
+    Prefix(String, String),
+    Regex(Regex, String),
+    CommonsUpload,
+}
+
+#[derive(Debug)]
+struct Rewrite {
+    target: Option<Position>,
+    kind: Kind,
+}
+
+#[derive(Debug)]
+pub struct Rewrites {
+    rewrites: Vec<Rewrite>,
+}
+
+/// Returns the IRI of a full IRI, e.g. `http://example.com/` or `<urn:example>`,
+/// or of a prefixed name, e.g. `wdt:P31`.
+fn iri(name: &str) -> Result<String, String> {
+    if let Some(iri) = name
+        .strip_prefix('<')
+        .and_then(|name| name.strip_suffix('>'))
+    {
+        return Ok(iri.to_string());
+    }
+    if name.contains("://") {
+        return Ok(name.to_string());
+    }
+    expand(name)
+}
+
+/// Returns the text of a regex replacement without its references to groups,
+/// e.g. `urn:` for `urn:$1` and `a$b` for `a$$b${c}`.
+fn replacement_text(replacement: &str) -> String {
+    let mut text = String::with_capacity(replacement.len());
+    let mut rest = replacement;
+    while let Some(index) = rest.find('$') {
+        text.push_str(&rest[..index]);
+        rest = &rest[index + 1..];
+        if let Some(after) = rest.strip_prefix('$') {
+            text.push('$');
+            rest = after;
+        } else if let Some((_, after)) = rest.strip_prefix('{').and_then(|r| r.split_once('}')) {
+            rest = after;
+        } else {
+            let end = rest
+                .find(|c: char| c != '_' && !c.is_ascii_alphanumeric())
+                .unwrap_or(rest.len());
+            if end == 0 {
+                text.push('$');
+            }
+            rest = &rest[end..];
+        }
+    }
+    text.push_str(rest);
+    text
+}
+
+impl Rewrites {
+    pub fn parse(source: &str) -> Result<Rewrites, String> {
+        let mut rewrites = Vec::new();
+        for (index, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let rewrite = Rewrites::parse_rewrite(line)
+                .map_err(|err| format!("line {}: {}", index + 1, err))?;
+            rewrites.push(rewrite);
+        }
+        Ok(Rewrites { rewrites })
+    }
+
+    fn parse_rewrite(line: &str) -> Result<Rewrite, String> {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let target = match parts[0] {
+            "subject" => Some(Position::Subject),
+            "predicate" => Some(Position::Predicate),
+            "object" => Some(Position::Object),
+            "iri" => None,
+            target => return Err(format!("unknown target: {}", target)),
+        };
+        let kind = match (parts.get(1).copied(), &parts[2.min(parts.len())..]) {
+            (Some("exact"), [from, to]) => Kind::Exact(iri(from)?, iri(to)?),
+            (Some("prefix"), [from, to]) => Kind::Prefix(iri(from)?, iri(to)?),
+            (Some("regex"), [regex, to]) => Kind::Regex(
+                Regex::new(regex).map_err(|err| format!("invalid regex: {}", err))?,
+                to.to_string(),
+            ),
+            (Some("commons-upload"), []) => Kind::CommonsUpload,
+            _ => return Err(format!("invalid rewrite: {}", line)),
+        };
+        let added = match &kind {
+            Kind::Exact(_, to) | Kind::Prefix(_, to) => to.clone(),
+            Kind::Regex(_, to) => replacement_text(to),
+            Kind::CommonsUpload => String::new(),
+        };
+        if added.contains(is_invalid_iri_char) {
+            return Err(format!("invalid IRI: {}", parts[3]));
+        }
+        Ok(Rewrite { target, kind })
+    }
+
+    /// Returns the rewritten IRI, if a rule matches.
+    fn rewrite_iri(&self, position: Position, iri: &str) -> Option<String> {
+        self.rewrites
+            .iter()
+            .filter(|rewrite| rewrite.target.is_none_or(|target| target == position))
+            .find_map(|rewrite| match &rewrite.kind {
+                Kind::Exact(from, to) => (iri == from).then(|| to.clone()),
+                Kind::Prefix(from, to) => iri
+                    .strip_prefix(from.as_str())
+                    .map(|rest| format!("{}{}", to, rest)),
+                Kind::Regex(regex, to) => regex
+                    .is_match(iri)
+                    .then(|| regex.replace(iri, to.as_str()).into_owned()),
+                Kind::CommonsUpload => commons_upload_url(iri),
+            })
+    }
+
+    /// Returns the prefix of entity IRIs in the object position after rewriting,
+    /// or an error if the rewrites change entity IRIs other than by their prefix.
+    pub fn entity_prefix(&self) -> Result<String, String> {
+        let mut prefixes = ["Q1", "P31", "L7-F2"].map(|id| {
+            let iri = format!("{}{}", ENTITY_IRI_PREFIX, id);
+            self.rewrite_iri(Position::Object, &iri)
+                .unwrap_or(iri)
+                .strip_suffix(id)
+                .map(str::to_string)
+        });
+        match &mut prefixes {
+            [Some(first), second, third]
+                if second.as_ref() == Some(first) && third.as_ref() == Some(first) =>
+            {
+                Ok(std::mem::take(first))
+            }
+            _ => Err("the rewrites change entity IRIs other than by their prefix".to_string()),
+        }
+    }
+
+    /// Returns the N-Triples line of the statement with rewritten IRIs, if any IRI was rewritten.
+    pub fn rewrite(&self, statement: Statement) -> Option<String> {
+        let subject = match statement.subject {
+            Subject::IRI(iri) => self.rewrite_iri(Position::Subject, iri),
+            Subject::Blank(_) => None,
+        };
+        let predicate = self.rewrite_iri(Position::Predicate, statement.predicate);
+        let object = match statement.object {
+            Object::IRI(iri) => self.rewrite_iri(Position::Object, iri),
+            _ => None,
+        };
+        if subject.is_none() && predicate.is_none() && object.is_none() {
+            return None;
+        }
+        let subject = match (subject.as_deref(), statement.subject) {
+            (Some(iri), _) => Subject::IRI(iri),
+            (None, subject) => subject,
+        };
+        let object = match (object.as_deref(), statement.object) {
+            (Some(iri), _) => Object::IRI(iri),
+            (None, object) => object,
+        };
+        let statement = Statement {
+            subject,
+            predicate: predicate.as_deref().unwrap_or(statement.predicate),
+            object,
+        };
+        Some(format!("{}\n", statement))
+    }
+}
+
+fn percent_decode_path(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        if bytes[index] == b'%' && index + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[index + 1..index + 3]).unwrap_or("");
+            if let Ok(byte) = u8::from_str_radix(hex, 16) {
+                decoded.push(byte);
+                index += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[index]);
+        index += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Returns the URL of the original file of a Commons file path IRI.
+/// Uploaded files are stored in directories named after the MD5 hash of the file name.
+fn commons_upload_url(iri: &str) -> Option<String> {
+    let file = iri
+        .strip_prefix(COMMONS_FILE_PATH_PREFIX)?
+        .replace("%20", "_");
+    let hash = format!("{:x}", md5::compute(percent_decode_path(&file)));
+    Some(format!(
+        "{}{}/{}/{}",
+        COMMONS_UPLOAD_PREFIX,
+        &hash[..1],
+        &hash[..2],
+        file
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{parse, RE};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_rewrite() {
+        let rewrites = Rewrites::parse(
+            r#"
+            # P31 as rdf:type
+            predicate exact wdt:P31 rdf:type
+            object    commons-upload
+            iri       prefix http://www.wikidata.org/entity/ https://www.wikidata.org/entity/
+            object    regex ^http://purl\.org/bncf/tid/(\d+)$ https://thes.bncf.firenze.sbn.it/termine.php?id=$1
+            "#,
+        )
+        .unwrap();
+        let rewrite = |line| rewrites.rewrite(parse(1, line, &RE));
+
+        assert_eq!(
+            rewrite(
+                r#"<http://www.wikidata.org/entity/Q8> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q9415> ."#
+            )
+            .as_deref(),
+            Some("<https://www.wikidata.org/entity/Q8> <http://www.w3.org/1999/02/22-rdf-syntax-ns#type> <https://www.wikidata.org/entity/Q9415> .\n")
+        );
+        assert_eq!(
+            rewrite(
+                r#"<http://www.wikidata.org/entity/Q8> <http://www.wikidata.org/prop/direct/P18> <http://commons.wikimedia.org/wiki/Special:FilePath/Happiness%20.jpg> ."#
+            )
+            .as_deref(),
+            Some("<https://www.wikidata.org/entity/Q8> <http://www.wikidata.org/prop/direct/P18> <https://upload.wikimedia.org/wikipedia/commons/c/c9/Happiness_.jpg> .\n")
+        );
+        assert_eq!(
+            rewrite(
+                r#"<http://www.wikidata.org/entity/Q8> <http://www.wikidata.org/prop/direct-normalized/P508> <http://purl.org/bncf/tid/13163> ."#
+            )
+            .as_deref(),
+            Some("<https://www.wikidata.org/entity/Q8> <http://www.wikidata.org/prop/direct-normalized/P508> <https://thes.bncf.firenze.sbn.it/termine.php?id=13163> .\n")
+        );
+        assert_eq!(
+            rewrite(r#"_:b1 <http://schema.org/name> "happiness"@en ."#),
+            None
+        );
+    }
+
+    #[test]
+    fn test_entity_prefix() {
+        let prefix = |source| Rewrites::parse(source).unwrap().entity_prefix();
+        assert_eq!(
+            prefix("object commons-upload").as_deref(),
+            Ok("http://www.wikidata.org/entity/")
+        );
+        assert_eq!(
+            prefix("iri prefix http://www.wikidata.org/entity/ https://example.org/").as_deref(),
+            Ok("https://example.org/")
+        );
+        assert!(prefix("object regex ^http://www.wikidata.org/entity/Q(.*)$ urn:$1").is_err());
+    }
+
+    #[test]
+    fn test_commons_upload_url() {
+        assert_eq!(
+            commons_upload_url(
+                "http://commons.wikimedia.org/wiki/Special:FilePath/Douglas%20adams%20portrait%20cropped.jpg"
+            )
+            .as_deref(),
+            Some("https://upload.wikimedia.org/wikipedia/commons/c/c0/Douglas_adams_portrait_cropped.jpg")
+        );
+    }
+
+    #[test]
+    fn test_percent_decode_path() {
+        assert_eq!(percent_decode_path("Caf%C3%A9_%aé.jpg"), "Café_%aé.jpg");
+        assert_eq!(percent_decode_path("100%"), "100%");
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            Rewrites::parse("predicate exact wdt:P31").err(),
+            Some("line 1: invalid rewrite: predicate exact wdt:P31".to_string())
+        );
+        assert_eq!(
+            Rewrites::parse("graph prefix a b").err(),
+            Some("line 1: unknown target: graph".to_string())
+        );
+        assert_eq!(
+            Rewrites::parse("object exact wd:Q1 http://example.com/{x}").err(),
+            Some("line 1: invalid IRI: http://example.com/{x}".to_string())
+        );
+        assert_eq!(
+            Rewrites::parse(r"object regex ^http://example\.com/(\w+)$ urn:$1>").err(),
+            Some(r"line 1: invalid IRI: urn:$1>".to_string())
+        );
+        assert_eq!(
+            Rewrites::parse("predicate exact wdtt:P31 rdf:type").err(),
+            Some("line 1: unknown prefix: wdtt".to_string())
+        );
+        assert!(Rewrites::parse("object exact wd:Q1 <urn:x>").is_ok());
+        assert_eq!(replacement_text("a$$b${c}$1_x$"), "a$b$");
+
+        // characters the IRI already has which are not allowed are percent-encoded
+        let rewrites = Rewrites::parse(
+            "iri prefix http:// https://\nobject regex ^urn:(.*)$ https://example.com/$1",
+        )
+        .unwrap();
+        let rewrite = |line| rewrites.rewrite(parse(1, line, &RE));
+        assert_eq!(
+            rewrite(
+                r#"<http://www.wikidata.org/entity/Q8> <http://www.wikidata.org/prop/direct/P973> <http://example.com/a b{c}> ."#
+            )
+            .as_deref(),
+            Some("<https://www.wikidata.org/entity/Q8> <https://www.wikidata.org/prop/direct/P973> <https://example.com/a%20b%7Bc%7D> .\n")
+        );
+        assert_eq!(
+            rewrite(r#"_:b1 <urn:p> <urn:a b> ."#).as_deref(),
+            Some("_:b1 <urn:p> <https://example.com/a%20b> .\n")
+        );
+    }
+}
@@ -0,0 +1,662 @@
+use crate::dates::DATE_TIME_IRI;
+use crate::{identifier_properties, languages, Extra, Object, Statement, Subject, PROPERTIES};
+use std::cmp::Ordering;
+use std::collections::HashSet;
+
+// Rules decide per statement whether it is kept, one rule per line:
+//
+// rule:       ("keep" | "drop") "if" condition
+// condition:  conjunction ("or" conjunction)*
+// conjunction: negation ("and" negation)*
+// negation:   "not" negation | "(" condition ")" | test
+// test:       operand (("==" | "!=" | "<" | "<=" | ">" | ">=" | "starts_with") operand
+//                      | "in" set | "not" "in" set)?
+// operand:    field | "string" | <iri> | prefix:name | number | "true" | "false"
+// set:        "@properties" | "@identifiers" | "@langs" | "[" operand ("," operand)* "]"
+//
+// Fields which do not apply to a statement, e.g. `object.lang` for an IRI object
+// or `year` for a literal which is not an `xsd:dateTime`,
+// make every comparison with them false, including `!=` and `not in`.
+
+static PREFIXES: [(&str, &str); 10] = [
+    ("rdf", "http://www.w3.org/1999/02/22-rdf-syntax-ns#"),
+    ("rdfs", "http://www.w3.org/2000/01/rdf-schema#"),
+    ("xsd", "http://www.w3.org/2001/XMLSchema#"),
+    ("skos", "http://www.w3.org/2004/02/skos/core#"),
+    ("schema", "http://schema.org/"),
+    ("geo", "http://www.opengis.net/ont/geosparql#"),
+    ("wikibase", "http://wikiba.se/ontology#"),
+    ("wd", "http://www.wikidata.org/entity/"),
+    ("wdt", "http://www.wikidata.org/prop/direct/"),
+    ("wdtn", "http://www.wikidata.org/prop/direct-normalized/"),
+];
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Action {
+    Keep,
+    Drop,
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+enum Field {
+    Subject,
+    SubjectBlank,
+    Predicate,
+    Object,
+    ObjectIri,
+    ObjectBlank,
+    ObjectLiteral,
+    ObjectLang,
+    ObjectDatatype,
+    Year,
+}
+
+#[derive(Debug, PartialEq)]
+enum Operand {
+    Field(Field),
+    String(String),
+    Number(i64),
+    Bool(bool),
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    StartsWith,
+}
+
+#[derive(Debug, PartialEq)]
+enum Set {
+    Properties,
+    Identifiers,
+    Langs,
+    List(HashSet<String>),
+}
+
+#[derive(Debug, PartialEq)]
+enum Condition {
+    Or(Box<Condition>, Box<Condition>),
+    And(Box<Condition>, Box<Condition>),
+    Not(Box<Condition>),
+    Compare(Operand, Comparison, Operand),
+    In(Operand, Set),
+    NotIn(Operand, Set),
+    Test(Operand),
+}
+
+#[derive(Debug, PartialEq)]
+struct Rule {
+    action: Action,
+    condition: Condition,
+}
+
+/// A compiled list of rules. The first rule with a matching condition decides,
+/// and statements without a matching rule are kept.
+#[derive(Debug, PartialEq)]
+pub struct Rules {
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+enum Value<'a> {
+    String(&'a str),
+    Number(i64),
+    Bool(bool),
+}
+
+impl Rules {
+    pub fn parse(source: &str) -> Result<Rules, String> {
+        let mut rules = Vec::new();
+        for (index, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let rule = Parser::new(line)
+                .and_then(|parser| parser.rule())
+                .map_err(|err| format!("line {}: {}", index + 1, err))?;
+            rules.push(rule);
+        }
+        Ok(Rules { rules })
+    }
+
+    pub fn decide(&self, statement: Statement) -> Action {
+        self.rules
+            .iter()
+            .find(|rule| rule.condition.matches(statement))
+            .map_or(Action::Keep, |rule| rule.action)
+    }
+}
+
+impl Condition {
+    fn matches(&self, statement: Statement) -> bool {
+        match self {
+            Condition::Or(left, right) => left.matches(statement) || right.matches(statement),
+            Condition::And(left, right) => left.matches(statement) && right.matches(statement),
+            Condition::Not(condition) => !condition.matches(statement),
+            Condition::Compare(left, comparison, right) => {
+                match (left.value(statement), right.value(statement)) {
+                    (Some(left), Some(right)) => compare(left, *comparison, right),
+                    _ => false,
+                }
+            }
+            Condition::In(operand, set) => operand
+                .value(statement)
+                .is_some_and(|value| set.contains(value)),
+            Condition::NotIn(operand, set) => operand
+                .value(statement)
+                .is_some_and(|value| !set.contains(value)),
+            Condition::Test(operand) => operand.value(statement) == Some(Value::Bool(true)),
+        }
+    }
+}
+
+fn compare(left: Value, comparison: Comparison, right: Value) -> bool {
+    let ordering = match (left, right) {
+        (Value::String(left), Value::String(right)) => {
+            if comparison == Comparison::StartsWith {
+                return left.starts_with(right);
+            }
+            left.cmp(right)
+        }
+        (Value::Number(left), Value::Number(right)) => left.cmp(&right),
+        (Value::Bool(left), Value::Bool(right)) => left.cmp(&right),
+        _ => return false,
+    };
+    match comparison {
+        Comparison::Eq => ordering == Ordering::Equal,
+        Comparison::Ne => ordering != Ordering::Equal,
+        Comparison::Lt => ordering == Ordering::Less,
+        Comparison::Le => ordering != Ordering::Greater,
+        Comparison::Gt => ordering == Ordering::Greater,
+        Comparison::Ge => ordering != Ordering::Less,
+        Comparison::StartsWith => false,
+    }
+}
+
+impl Set {
+    fn contains(&self, value: Value) -> bool {
+        let Value::String(value) = value else {
+            return false;
+        };
+        match self {
+            Set::Properties => PROPERTIES.contains(value),
+            Set::Identifiers => identifier_properties().contains(value),
+            Set::Langs => languages().contains(value),
+            Set::List(values) => values.contains(value),
+        }
+    }
+}
+
+impl Operand {
+    fn value<'a>(&'a self, statement: Statement<'a>) -> Option<Value<'a>> {
+        match self {
+            Operand::Field(field) => field.value(statement),
+            Operand::String(string) => Some(Value::String(string)),
+            Operand::Number(number) => Some(Value::Number(*number)),
+            Operand::Bool(bool) => Some(Value::Bool(*bool)),
+        }
+    }
+}
+
+impl Field {
+    fn parse(name: &str) -> Option<Field> {
+        let field = match name {
+            "subject" => Field::Subject,
+            "subject.blank" => Field::SubjectBlank,
+            "predicate" => Field::Predicate,
+            "object" => Field::Object,
+            "object.iri" => Field::ObjectIri,
+            "object.blank" => Field::ObjectBlank,
+            "object.literal" => Field::ObjectLiteral,
+            "object.lang" => Field::ObjectLang,
+            "object.datatype" => Field::ObjectDatatype,
+            "year" => Field::Year,
+            _ => return None,
+        };
+        Some(field)
+    }
+
+    fn value<'a>(self, statement: Statement<'a>) -> Option<Value<'a>> {
+        let value = match (self, statement.object) {
+            (Field::Subject, _) => match statement.subject {
+                Subject::IRI(value) | Subject::Blank(value) => Value::String(value),
+            },
+            (Field::SubjectBlank, _) => Value::Bool(matches!(statement.subject, Subject::Blank(_))),
+            (Field::Predicate, _) => Value::String(statement.predicate),
+            (
+                Field::Object,
+                Object::IRI(value) | Object::Blank(value) | Object::Literal(value, _),
+            ) => Value::String(value),
+            (Field::ObjectIri, object) => Value::Bool(matches!(object, Object::IRI(_))),
+            (Field::ObjectBlank, object) => Value::Bool(matches!(object, Object::Blank(_))),
+            (Field::ObjectLiteral, object) => Value::Bool(matches!(object, Object::Literal(..))),
+            (Field::ObjectLang, Object::Literal(_, Extra::Lang(lang))) => Value::String(lang),
+            (Field::ObjectDatatype, Object::Literal(_, Extra::Type(data_type))) => {
+                Value::String(data_type)
+            }
+            (Field::Year, Object::Literal(literal, Extra::Type(data_type)))
+                if data_type == DATE_TIME_IRI =>
+            {
+                Value::Number(year(literal)?)
+            }
+            _ => return None,
+        };
+        Some(value)
+    }
+}
+
+/// Returns the year of a date literal, e.g. `-44` for `-0044-03-15T00:00:00Z`.
+fn year(literal: &str) -> Option<i64> {
+    let digits = literal.trim_start_matches(['+', '-']);
+    let end = digits.find('-')?;
+    if end == 0 || !digits[..end].bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let year: i64 = digits[..end].parse().ok()?;
+    Some(if literal.starts_with('-') {
+        -year
+    } else {
+        year
+    })
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum Token {
+    Word(String),
+    String(String),
+    Iri(String),
+    Number(i64),
+    Set(String),
+    Comparison(Comparison),
+    Open,
+    Close,
+    OpenList,
+    CloseList,
+    Comma,
+}
+
+fn tokenize(line: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = line.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        let token = match c {
+            '(' | ')' | '[' | ']' | ',' => {
+                chars.next();
+                match c {
+                    '(' => Token::Open,
+                    ')' => Token::Close,
+                    '[' => Token::OpenList,
+                    ']' => Token::CloseList,
+                    _ => Token::Comma,
+                }
+            }
+            '"' => {
+                chars.next();
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => string.push(chars.next().ok_or("unterminated string")?),
+                        Some(c) => string.push(c),
+                        None => return Err("unterminated string".to_string()),
+                    }
+                }
+                Token::String(string)
+            }
+            '=' | '!' | '>' | '<' => {
+                chars.next();
+                let equals = chars.next_if_eq(&'=').is_some();
+                match (c, equals) {
+                    ('=', true) => Token::Comparison(Comparison::Eq),
+                    ('!', true) => Token::Comparison(Comparison::Ne),
+                    ('>', false) => Token::Comparison(Comparison::Gt),
+                    ('>', true) => Token::Comparison(Comparison::Ge),
+                    ('<', true) => Token::Comparison(Comparison::Le),
+                    // an IRI starts with its scheme, so e.g. `year<1900` is a comparison
+                    ('<', false) if chars.peek().is_none_or(|c| !c.is_ascii_alphabetic()) => {
+                        Token::Comparison(Comparison::Lt)
+                    }
+                    ('<', false) => {
+                        let mut iri = String::new();
+                        loop {
+                            match chars.next() {
+                                Some('>') => break,
+                                Some(c) if !c.is_whitespace() => iri.push(c),
+                                _ => return Err(format!("unterminated IRI: <{}", iri)),
+                            }
+                        }
+                        Token::Iri(iri)
+                    }
+                    _ => return Err(format!("invalid operator: {}", c)),
+                }
+            }
+            '@' => {
+                chars.next();
+                Token::Set(word(&mut chars))
+            }
+            c if c.is_ascii_digit() || c == '-' => {
+                chars.next();
+                let number = format!("{}{}", c, word(&mut chars));
+                Token::Number(
+                    number
+                        .parse()
+                        .map_err(|_| format!("invalid number: {}", number))?,
+                )
+            }
+            c if c.is_alphabetic() => Token::Word(word(&mut chars)),
+            c => return Err(format!("unexpected character: {}", c)),
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+fn word<I: Iterator<Item = char>>(chars: &mut std::iter::Peekable<I>) -> String {
+    let mut word = String::new();
+    while let Some(c) = chars.next_if(|c| c.is_alphanumeric() || matches!(c, '_' | '.' | ':')) {
+        word.push(c);
+    }
+    word
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn new(line: &str) -> Result<Parser, String> {
+        Ok(Parser {
+            tokens: tokenize(line)?,
+            position: 0,
+        })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Result<Token, String> {
+        let token = self.peek().cloned().ok_or("unexpected end of rule")?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn next_if_word(&mut self, word: &str) -> bool {
+        if self.peek() == Some(&Token::Word(word.to_string())) {
+            self.position += 1;
+            return true;
+        }
+        false
+    }
+
+    fn rule(mut self) -> Result<Rule, String> {
+        let action = match self.next()? {
+            Token::Word(word) if word == "keep" => Action::Keep,
+            Token::Word(word) if word == "drop" => Action::Drop,
+            token => return Err(format!("expected keep or drop, got {:?}", token)),
+        };
+        if !self.next_if_word("if") {
+            return Err("expected if".to_string());
+        }
+        let condition = self.condition()?;
+        if let Some(token) = self.peek() {
+            return Err(format!("unexpected {:?}", token));
+        }
+        Ok(Rule { action, condition })
+    }
+
+    fn condition(&mut self) -> Result<Condition, String> {
+        let mut condition = self.conjunction()?;
+        while self.next_if_word("or") {
+            condition = Condition::Or(Box::new(condition), Box::new(self.conjunction()?));
+        }
+        Ok(condition)
+    }
+
+    fn conjunction(&mut self) -> Result<Condition, String> {
+        let mut condition = self.negation()?;
+        while self.next_if_word("and") {
+            condition = Condition::And(Box::new(condition), Box::new(self.negation()?));
+        }
+        Ok(condition)
+    }
+
+    fn negation(&mut self) -> Result<Condition, String> {
+        if self.next_if_word("not") {
+            return Ok(Condition::Not(Box::new(self.negation()?)));
+        }
+        if self.peek() == Some(&Token::Open) {
+            self.position += 1;
+            let condition = self.condition()?;
+            if self.next()? != Token::Close {
+                return Err("expected )".to_string());
+            }
+            return Ok(condition);
+        }
+        self.test()
+    }
+
+    fn test(&mut self) -> Result<Condition, String> {
+        let operand = self.operand()?;
+        if let Some(Token::Comparison(comparison)) = self.peek() {
+            let comparison = *comparison;
+            self.position += 1;
+            return Ok(Condition::Compare(operand, comparison, self.operand()?));
+        }
+        if self.next_if_word("starts_with") {
+            return Ok(Condition::Compare(
+                operand,
+                Comparison::StartsWith,
+                self.operand()?,
+            ));
+        }
+        if self.next_if_word("in") {
+            return Ok(Condition::In(operand, self.set()?));
+        }
+        if self.next_if_word("not") {
+            if !self.next_if_word("in") {
+                return Err("expected in".to_string());
+            }
+            return Ok(Condition::NotIn(operand, self.set()?));
+        }
+        match operand {
+            Operand::Field(_) | Operand::Bool(_) => Ok(Condition::Test(operand)),
+            _ => Err(format!("expected a condition, got {:?}", operand)),
+        }
+    }
+
+    fn operand(&mut self) -> Result<Operand, String> {
+        let operand = match self.next()? {
+            Token::String(string) | Token::Iri(string) => Operand::String(string),
+            Token::Number(number) => Operand::Number(number),
+            Token::Word(word) if word == "true" => Operand::Bool(true),
+            Token::Word(word) if word == "false" => Operand::Bool(false),
+            Token::Word(word) => match Field::parse(&word) {
+                Some(field) => Operand::Field(field),
+                None => Operand::String(expand(&word)?),
+            },
+            token => return Err(format!("expected an operand, got {:?}", token)),
+        };
+        Ok(operand)
+    }
+
+    fn set(&mut self) -> Result<Set, String> {
+        match self.next()? {
+            Token::Set(name) => match name.as_str() {
+                "properties" => Ok(Set::Properties),
+                "identifiers" => Ok(Set::Identifiers),
+                "langs" => Ok(Set::Langs),
+                _ => Err(format!("unknown set: @{}", name)),
+            },
+            Token::OpenList => {
+                let mut values = HashSet::new();
+                loop {
+                    match self.operand()? {
+                        Operand::String(value) => values.insert(value),
+                        operand => return Err(format!("expected a string, got {:?}", operand)),
+                    };
+                    match self.next()? {
+                        Token::Comma => continue,
+                        Token::CloseList => break,
+                        token => return Err(format!("expected , or ], got {:?}", token)),
+                    }
+                }
+                Ok(Set::List(values))
+            }
+            token => Err(format!("expected a set, got {:?}", token)),
+        }
+    }
+}
+
+/// Expands a prefixed name, e.g. `xsd:dateTime`.
+pub(crate) fn expand(name: &str) -> Result<String, String> {
+    let (prefix, local) = name
+        .split_once(':')
+        .ok_or_else(|| format!("unknown field: {}", name))?;
+    PREFIXES
+        .iter()
+        .find(|(known, _)| *known == prefix)
+        .map(|(_, iri)| format!("{}{}", iri, local))
+        .ok_or_else(|| format!("unknown prefix: {}", prefix))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{parse, RE};
+    use pretty_assertions::assert_eq;
+
+    fn decide(rules: &Rules, line: &str) -> Action {
+        rules.decide(parse(1, line, &RE))
+    }
+
+    #[test]
+    fn test_rules() {
+        let rules = Rules::parse(
+            r#"
+            # comments and empty lines are ignored
+            keep if object.datatype == xsd:dateTime and year >= 1900
+            drop if object.datatype == xsd:dateTime
+            drop if predicate in [wdt:P18, <http://www.wikidata.org/prop/direct/P373>]
+            drop if not (object.iri or object.lang == "en")
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            decide(
+                &rules,
+                r#"<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P571> "+1952-03-11T00:00:00Z"^^<http://www.w3.org/2001/XMLSchema#dateTime> ."#
+            ),
+            Action::Keep
+        );
+        assert_eq!(
+            decide(
+                &rules,
+                r#"<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P571> "-13798000000-00-00T00:00:00Z"^^<http://www.w3.org/2001/XMLSchema#dateTime> ."#
+            ),
+            Action::Drop
+        );
+        assert_eq!(
+            decide(
+                &rules,
+                r#"<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P373> "Universe" ."#
+            ),
+            Action::Drop
+        );
+        assert_eq!(
+            decide(
+                &rules,
+                r#"<http://www.wikidata.org/entity/Q1> <http://schema.org/name> "Universum"@de ."#
+            ),
+            Action::Drop
+        );
+        assert_eq!(
+            decide(
+                &rules,
+                r#"<http://www.wikidata.org/entity/Q1> <http://schema.org/name> "universe"@en ."#
+            ),
+            Action::Keep
+        );
+    }
+
+    #[test]
+    fn test_missing_fields() {
+        let rules =
+            Rules::parse("drop if object.lang not in @langs or object.lang != \"en\"").unwrap();
+        assert_eq!(
+            decide(
+                &rules,
+                r#"<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q36906466> ."#
+            ),
+            Action::Keep
+        );
+    }
+
+    #[test]
+    fn test_year() {
+        assert_eq!(year("+1952-03-11T00:00:00Z"), Some(1952));
+        assert_eq!(year("-0044-03-15T00:00:00Z"), Some(-44));
+        assert_eq!(year("2019-08-28"), Some(2019));
+        assert_eq!(year("42"), None);
+
+        for source in [
+            "drop if year <1900",
+            "drop if year<1900",
+            "drop if year < 1900",
+        ] {
+            let rules = Rules::parse(source).unwrap();
+            assert_eq!(
+                decide(
+                    &rules,
+                    r#"<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P571> "+1852-03-11T00:00:00Z"^^<http://www.w3.org/2001/XMLSchema#dateTime> ."#
+                ),
+                Action::Drop
+            );
+            // only dates have a year
+            assert_eq!(
+                decide(
+                    &rules,
+                    r#"<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P1234> "1852-1-5"^^<http://www.w3.org/2001/XMLSchema#string> ."#
+                ),
+                Action::Keep
+            );
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            Rules::parse("keep predicate == wdt:P31"),
+            Err("line 1: expected if".to_string())
+        );
+        assert_eq!(
+            Rules::parse("\ndrop if foo:bar"),
+            Err("line 2: unknown prefix: foo".to_string())
+        );
+        assert_eq!(
+            Rules::parse("drop if predicate in @unknown"),
+            Err("line 1: unknown set: @unknown".to_string())
+        );
+        assert_eq!(
+            Rules::parse("drop if predicate == <http://schema.org/name"),
+            Err("line 1: unterminated IRI: <http://schema.org/name".to_string())
+        );
+        assert_eq!(
+            Rules::parse("drop if predicate == <http://schema.org/ name>"),
+            Err("line 1: unterminated IRI: <http://schema.org/".to_string())
+        );
+    }
+}
@@ -0,0 +1,406 @@
+use crate::counts::Counts;
+use crate::{encode_iri, is_invalid_iri_char, Extra, Object, Statement, Subject};
+use clap::ValueEnum;
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::fmt::{self, Display, Formatter};
+use std::io::Write;
+
+static XSD_PREFIX: &str = "http://www.w3.org/2001/XMLSchema#";
+
+lazy_static! {
+    static ref DATE_RE: Regex = Regex::new(
+        r"^([+-]?)(\d+)-(\d{2})-(\d{2})(T\d{2}:\d{2}:\d{2}(?:\.\d+)?(?:Z|[+-]\d{2}:\d{2})?)?$"
+    )
+    .unwrap();
+}
+
+/// The triple stores with known limits on the literals and IRIs they load
+#[derive(ValueEnum, Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Store {
+    /// Rejects `+` signs and year 0 in dates, i.e. only accepts XML Schema 1.0 dates
+    Blazegraph,
+    /// Rejects `+` signs and year 0 in dates, years beyond 4 digits, and decimals beyond 40 digits
+    Virtuoso,
+    /// Only rejects invalid IRIs and dates
+    Qlever,
+}
+
+#[derive(ValueEnum, Debug, PartialEq, Eq, Copy, Clone)]
+pub enum SanitizeAction {
+    /// Repair the triples where possible, e.g. remove the `+` sign of a year, and drop the others
+    Repair,
+    /// Drop the triples
+    Drop,
+    /// Write the triples to `quarantine_<n>.nt.bz2` instead
+    Quarantine,
+}
+
+/// Why a store would reject a triple
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
+pub enum Reason {
+    InvalidIri,
+    InvalidDate,
+    PlusSign,
+    YearZero,
+    ZeroMonthOrDay,
+    DateRange,
+    HugeDecimal,
+}
+
+impl Display for Reason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Reason::InvalidIri => "invalid-iri",
+            Reason::InvalidDate => "invalid-date",
+            Reason::PlusSign => "plus-sign",
+            Reason::YearZero => "year-zero",
+            Reason::ZeroMonthOrDay => "zero-month-or-day",
+            Reason::DateRange => "date-range",
+            Reason::HugeDecimal => "huge-decimal",
+        })
+    }
+}
+
+/// What happened to a triple a store would reject
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
+pub enum Outcome {
+    Repaired,
+    Dropped,
+    Quarantined,
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Outcome::Repaired => "repaired",
+            Outcome::Dropped => "dropped",
+            Outcome::Quarantined => "quarantined",
+        })
+    }
+}
+
+pub enum Sanitized {
+    Valid,
+    Repaired(String),
+    Rejected,
+}
+
+/// The problems with a part of a triple, and the repaired part, if it can be repaired
+type Problem = (Vec<Reason>, Option<String>);
+
+#[derive(Debug, Copy, Clone)]
+pub struct Sanitizer {
+    plus_sign: bool,
+    year_zero: bool,
+    max_year_digits: usize,
+    max_decimal_digits: usize,
+    pub action: SanitizeAction,
+}
+
+impl Sanitizer {
+    pub fn new(store: Store, action: SanitizeAction) -> Sanitizer {
+        let (plus_sign, year_zero, max_year_digits, max_decimal_digits) = match store {
+            Store::Blazegraph => (false, false, usize::MAX, usize::MAX),
+            Store::Virtuoso => (false, false, 4, 40),
+            Store::Qlever => (true, true, usize::MAX, usize::MAX),
+        };
+        Sanitizer {
+            plus_sign,
+            year_zero,
+            max_year_digits,
+            max_decimal_digits,
+            action,
+        }
+    }
+
+    /// Returns all the reasons why the store would reject the statement, if it would,
+    /// and the repaired N-Triples line, if the statement can be repaired.
+    pub fn check(&self, statement: Statement) -> Option<Problem> {
+        let mut reasons = Vec::new();
+        let mut repairable = true;
+        let mut note = |problem: Option<Problem>| {
+            let (problems, repaired) = problem?;
+            reasons.extend(problems);
+            repairable &= repaired.is_some();
+            repaired
+        };
+
+        let subject = match statement.subject {
+            Subject::IRI(iri) => note(check_iri(iri)),
+            Subject::Blank(_) => None,
+        };
+        let predicate = note(check_iri(statement.predicate));
+        let (object, data_type) = match statement.object {
+            Object::IRI(iri) => (note(check_iri(iri)), None),
+            Object::Literal(literal, Extra::Type(data_type)) => (
+                note(self.check_literal(literal, data_type)),
+                note(check_iri(data_type)),
+            ),
+            _ => (None, None),
+        };
+
+        if reasons.is_empty() {
+            return None;
+        }
+        reasons.sort();
+        reasons.dedup();
+        if !repairable {
+            return Some((reasons, None));
+        }
+        let statement = Statement {
+            subject: subject.as_deref().map_or(statement.subject, Subject::IRI),
+            predicate: predicate.as_deref().unwrap_or(statement.predicate),
+            object: match statement.object {
+                Object::IRI(iri) => Object::IRI(object.as_deref().unwrap_or(iri)),
+                Object::Literal(literal, Extra::Type(iri)) => Object::Literal(
+                    object.as_deref().unwrap_or(literal),
+                    Extra::Type(data_type.as_deref().unwrap_or(iri)),
+                ),
+                object => object,
+            },
+        };
+        Some((reasons, Some(format!("{}\n", statement))))
+    }
+
+    fn check_literal(&self, literal: &str, data_type: &str) -> Option<Problem> {
+        match data_type.strip_prefix(XSD_PREFIX)? {
+            "dateTime" | "date" => self.check_date(literal),
+            "decimal" | "integer" => {
+                let digits = literal.chars().filter(char::is_ascii_digit).count();
+                (digits > self.max_decimal_digits).then(|| (vec![Reason::HugeDecimal], None))
+            }
+            _ => None,
+        }
+    }
+
+    fn check_date(&self, literal: &str) -> Option<Problem> {
+        let Some(captures) = DATE_RE.captures(literal) else {
+            return Some((vec![Reason::InvalidDate], None));
+        };
+        let sign = &captures[1];
+        let year = captures[2].trim_start_matches('0');
+        let (month, day) = (&captures[3], &captures[4]);
+        let time = captures.get(5).map_or("", |time| time.as_str());
+        if month > "12" || day > "31" {
+            return Some((vec![Reason::InvalidDate], None));
+        }
+        let mut reasons = Vec::new();
+        if year.len() > self.max_year_digits {
+            reasons.push(Reason::DateRange);
+        }
+        if year.is_empty() && !self.year_zero {
+            reasons.push(Reason::YearZero);
+        }
+        let repairable = reasons.is_empty();
+        if month == "00" || day == "00" {
+            reasons.push(Reason::ZeroMonthOrDay);
+        }
+        let plus_sign = sign == "+" && !self.plus_sign;
+        if plus_sign {
+            reasons.push(Reason::PlusSign);
+        }
+        if reasons.is_empty() {
+            return None;
+        }
+        let repaired = repairable.then(|| {
+            format!(
+                "{}{}-{}-{}{}",
+                if plus_sign { "" } else { sign },
+                &captures[2],
+                if month == "00" { "01" } else { month },
+                if day == "00" { "01" } else { day },
+                time
+            )
+        });
+        Some((reasons, repaired))
+    }
+
+    /// Decides what happens to the statement, if the store would reject it,
+    /// and counts the outcome per reason.
+    pub fn sanitize<T: Write>(
+        &self,
+        quarantine: Option<&mut T>,
+        counts: &mut Counts<(Reason, Outcome)>,
+        line: &str,
+        statement: Statement,
+    ) -> Sanitized {
+        let Some((reasons, repaired)) = self.check(statement) else {
+            return Sanitized::Valid;
+        };
+        let (outcome, sanitized) = match (self.action, repaired, quarantine) {
+            (SanitizeAction::Repair, Some(repaired), _) => {
+                (Outcome::Repaired, Sanitized::Repaired(repaired))
+            }
+            (SanitizeAction::Quarantine, _, Some(quarantine)) => {
+                quarantine.write_all(line.as_bytes()).unwrap();
+                (Outcome::Quarantined, Sanitized::Rejected)
+            }
+            _ => (Outcome::Dropped, Sanitized::Rejected),
+        };
+        for reason in reasons {
+            counts.increment((reason, outcome));
+        }
+        sanitized
+    }
+}
+
+/// Percent-encodes the characters which are not allowed in IRIs, e.g. spaces and braces.
+fn check_iri(iri: &str) -> Option<Problem> {
+    if !iri.contains(is_invalid_iri_char) {
+        return None;
+    }
+    Some((vec![Reason::InvalidIri], Some(encode_iri(iri).into_owned())))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{parse, RE};
+    use pretty_assertions::assert_eq;
+
+    fn check(sanitizer: &Sanitizer, line: &str) -> Option<Problem> {
+        sanitizer.check(parse(1, line, &RE))
+    }
+
+    #[test]
+    fn test_dates() {
+        let virtuoso = Sanitizer::new(Store::Virtuoso, SanitizeAction::Repair);
+        let qlever = Sanitizer::new(Store::Qlever, SanitizeAction::Repair);
+
+        let line = r#"<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P571> "+1952-03-11T00:00:00Z"^^<http://www.w3.org/2001/XMLSchema#dateTime> ."#;
+        assert_eq!(
+            check(&virtuoso, line),
+            Some((
+                vec![Reason::PlusSign],
+                Some(r#"<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P571> "1952-03-11T00:00:00Z"^^<http://www.w3.org/2001/XMLSchema#dateTime> .
+"#.to_string())
+            ))
+        );
+        assert_eq!(check(&qlever, line), None);
+
+        let line = r#"<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P580> "-13798000000-00-00T00:00:00Z"^^<http://www.w3.org/2001/XMLSchema#dateTime> ."#;
+        assert_eq!(
+            check(&virtuoso, line),
+            Some((vec![Reason::ZeroMonthOrDay, Reason::DateRange], None))
+        );
+        assert_eq!(
+            check(&qlever, line),
+            Some((
+                vec![Reason::ZeroMonthOrDay],
+                Some(r#"<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P580> "-13798000000-01-01T00:00:00Z"^^<http://www.w3.org/2001/XMLSchema#dateTime> .
+"#.to_string())
+            ))
+        );
+
+        let line = r#"<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P580> "0000-01-01T00:00:00Z"^^<http://www.w3.org/2001/XMLSchema#dateTime> ."#;
+        assert_eq!(check(&virtuoso, line), Some((vec![Reason::YearZero], None)));
+        assert_eq!(check(&qlever, line), None);
+
+        let line = r#"<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P580> "+0000-01-01T00:00:00Z"^^<http://www.w3.org/2001/XMLSchema#dateTime> ."#;
+        assert_eq!(
+            check(&virtuoso, line),
+            Some((vec![Reason::PlusSign, Reason::YearZero], None))
+        );
+
+        let line = r#"<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P580> "1952-13-01"^^<http://www.w3.org/2001/XMLSchema#date> ."#;
+        assert_eq!(
+            check(&qlever, line),
+            Some((vec![Reason::InvalidDate], None))
+        );
+    }
+
+    #[test]
+    fn test_iris_and_decimals() {
+        let virtuoso = Sanitizer::new(Store::Virtuoso, SanitizeAction::Repair);
+        assert_eq!(
+            check(
+                &virtuoso,
+                r#"<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P856> <http://example.com/a b{c}> ."#
+            ),
+            Some((
+                vec![Reason::InvalidIri],
+                Some("<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P856> <http://example.com/a%20b%7Bc%7D> .\n".to_string())
+            ))
+        );
+        assert_eq!(
+            check(
+                &virtuoso,
+                r#"<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P1082> "+12345678901234567890123456789012345678901"^^<http://www.w3.org/2001/XMLSchema#decimal> ."#
+            ),
+            Some((vec![Reason::HugeDecimal], None))
+        );
+        assert_eq!(
+            check(
+                &virtuoso,
+                r#"<http://www.wikidata.org/entity/Q1644> <http://www.wikidata.org/prop/direct/P2043> "+1094.26"^^<http://www.w3.org/2001/XMLSchema#decimal> ."#
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_sanitize() {
+        let line = "<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P580> \"0000-01-01T00:00:00Z\"^^<http://www.w3.org/2001/XMLSchema#dateTime> .\n";
+        let statement = parse(1, line, &RE);
+        let mut counts = Counts::default();
+        let mut quarantine = Vec::new();
+
+        let sanitizer = Sanitizer::new(Store::Blazegraph, SanitizeAction::Quarantine);
+        assert!(matches!(
+            sanitizer.sanitize(Some(&mut quarantine), &mut counts, line, statement),
+            Sanitized::Rejected
+        ));
+        let sanitizer = Sanitizer::new(Store::Blazegraph, SanitizeAction::Repair);
+        assert!(matches!(
+            sanitizer.sanitize(Some(&mut quarantine), &mut counts, line, statement),
+            Sanitized::Rejected
+        ));
+
+        assert_eq!(String::from_utf8(quarantine).unwrap(), line);
+        assert_eq!(
+            counts.into_sorted(),
+            vec![
+                ((Reason::YearZero, Outcome::Dropped), 1),
+                ((Reason::YearZero, Outcome::Quarantined), 1)
+            ]
+        );
+
+        // a triple counts for each of its reasons
+        let line = "<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P571> \"+2019-00-00T00:00:00Z\"^^<http://www.w3.org/2001/XMLSchema#dateTime> .\n";
+        let mut counts = Counts::default();
+        let sanitizer = Sanitizer::new(Store::Virtuoso, SanitizeAction::Repair);
+        assert!(matches!(
+            sanitizer.sanitize(None::<&mut Vec<u8>>, &mut counts, line, parse(1, line, &RE)),
+            Sanitized::Repaired(repaired) if repaired.contains("\"2019-01-01T00:00:00Z\"")
+        ));
+        assert_eq!(
+            counts.into_sorted(),
+            vec![
+                ((Reason::PlusSign, Outcome::Repaired), 1),
+                ((Reason::ZeroMonthOrDay, Outcome::Repaired), 1)
+            ]
+        );
+
+        let line = "<http://example.com/a b> <http://www.wikidata.org/prop/direct/P571> \"+1952-03-11T00:00:00Z\"^^<http://www.w3.org/2001/XMLSchema#dateTime> .\n";
+        let mut counts = Counts::default();
+        let sanitizer = Sanitizer::new(Store::Virtuoso, SanitizeAction::Repair);
+        let Sanitized::Repaired(repaired) =
+            sanitizer.sanitize(None::<&mut Vec<u8>>, &mut counts, line, parse(1, line, &RE))
+        else {
+            panic!("expected a repaired triple");
+        };
+        assert_eq!(
+            repaired,
+            "<http://example.com/a%20b> <http://www.wikidata.org/prop/direct/P571> \"1952-03-11T00:00:00Z\"^^<http://www.w3.org/2001/XMLSchema#dateTime> .\n"
+        );
+        assert_eq!(
+            counts.into_sorted(),
+            vec![
+                ((Reason::InvalidIri, Outcome::Repaired), 1),
+                ((Reason::PlusSign, Outcome::Repaired), 1)
+            ]
+        );
+    }
+}
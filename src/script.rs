@@ -0,0 +1,313 @@
+use crate::{is_invalid_iri_char, try_unescape, Extra, Object, Statement, Subject};
+use rhai::{Array, Dynamic, Engine, Map, Scope, AST};
+use std::io::Write;
+
+static TRANSFORM_FUNCTION: &str = "transform";
+
+/// A script which transforms each statement written to the RDF output.
+///
+/// The script defines a function `transform(statement)`, which receives a map with the keys
+/// `subject`, `subject_type` (`"iri"` or `"blank"`), `predicate`, `object`,
+/// `object_type` (`"iri"`, `"blank"` or `"literal"`), and `lang` and `datatype` for literals.
+/// Values are as in the dump, e.g. literals are escaped. The function returns
+/// `true` to keep the statement, `false` to drop it, a map to replace it,
+/// or an array of maps to replace it with any number of statements.
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    /// The line of the source the function is defined on, for errors in the values it returns
+    line: usize,
+}
+
+impl Script {
+    pub fn compile(source: &str) -> Result<Script, String> {
+        let engine = Engine::new();
+        let ast = engine.compile(source).map_err(|err| err.to_string())?;
+        if !ast
+            .iter_functions()
+            .any(|function| function.name == TRANSFORM_FUNCTION && function.params.len() == 1)
+        {
+            return Err(format!(
+                "missing function {}(statement)",
+                TRANSFORM_FUNCTION
+            ));
+        }
+        let definition = format!("fn {}", TRANSFORM_FUNCTION);
+        let line = source
+            .lines()
+            .position(|line| line.contains(&definition))
+            .map_or(0, |index| index + 1);
+        Ok(Script { engine, ast, line })
+    }
+
+    fn call(&self, statement: Statement) -> Result<Dynamic, String> {
+        self.engine
+            .call_fn(
+                &mut Scope::new(),
+                &self.ast,
+                TRANSFORM_FUNCTION,
+                (statement_map(statement),),
+            )
+            .map_err(|err| err.to_string())
+    }
+
+    /// Writes the statements the script returns for the statement.
+    /// Errors refer to the line of the script, as the same error usually occurs for many statements.
+    pub fn write<T: Write>(
+        &self,
+        writer: &mut T,
+        line: &str,
+        statement: Statement,
+    ) -> Result<(), String> {
+        let result = self.call(statement)?;
+        let invalid = |err: &str| {
+            format!(
+                "invalid result of {} (line {}): {}",
+                TRANSFORM_FUNCTION, self.line, err
+            )
+        };
+        let triples = if let Some(keep) = result.clone().try_cast::<bool>() {
+            if keep {
+                writer.write_all(line.as_bytes()).unwrap();
+            }
+            return Ok(());
+        } else if let Some(map) = result.clone().try_cast::<Map>() {
+            vec![map]
+        } else if let Some(array) = result.try_cast::<Array>() {
+            array
+                .into_iter()
+                .map(|value| {
+                    value
+                        .try_cast::<Map>()
+                        .ok_or_else(|| invalid("expected maps"))
+                })
+                .collect::<Result<_, _>>()?
+        } else {
+            return Err(invalid("expected a bool, map or array"));
+        };
+        for triple in triples {
+            let triple = triple_line(&triple).map_err(|err| invalid(&err))?;
+            writer.write_all(triple.as_bytes()).unwrap();
+        }
+        Ok(())
+    }
+}
+
+fn statement_map(statement: Statement) -> Map {
+    let mut map = Map::new();
+    let (subject, subject_type) = match statement.subject {
+        Subject::IRI(iri) => (iri, "iri"),
+        Subject::Blank(blank) => (blank, "blank"),
+    };
+    map.insert("subject".into(), subject.into());
+    map.insert("subject_type".into(), subject_type.into());
+    map.insert("predicate".into(), statement.predicate.into());
+    let (object, object_type) = match statement.object {
+        Object::IRI(iri) => (iri, "iri"),
+        Object::Blank(blank) => (blank, "blank"),
+        Object::Literal(literal, extra) => {
+            match extra {
+                Extra::Lang(lang) => map.insert("lang".into(), lang.into()),
+                Extra::Type(data_type) => map.insert("datatype".into(), data_type.into()),
+                Extra::None => None,
+            };
+            (literal, "literal")
+        }
+    };
+    map.insert("object".into(), object.into());
+    map.insert("object_type".into(), object_type.into());
+    map
+}
+
+fn string(map: &Map, key: &str) -> Result<Option<String>, String> {
+    match map.get(key) {
+        None => Ok(None),
+        Some(value) if value.is_unit() => Ok(None),
+        Some(value) => value
+            .clone()
+            .into_string()
+            .map(Some)
+            .map_err(|_| format!("{} must be a string", key)),
+    }
+}
+
+fn required(map: &Map, key: &str) -> Result<String, String> {
+    string(map, key)?.ok_or_else(|| format!("missing {}", key))
+}
+
+fn subject<'a>(value: &'a str, kind: Option<&str>) -> Result<Subject<'a>, String> {
+    match kind {
+        None | Some("iri") => Ok(Subject::IRI(iri(value)?)),
+        Some("blank") if !value.is_empty() && !value.contains(char::is_whitespace) => {
+            Ok(Subject::Blank(value))
+        }
+        Some("blank") => Err(format!("invalid blank node: {}", value)),
+        Some(kind) => Err(format!("invalid type: {}", kind)),
+    }
+}
+
+fn is_lang(lang: &str) -> bool {
+    lang.split('-').enumerate().all(|(index, subtag)| {
+        !subtag.is_empty()
+            && subtag
+                .bytes()
+                .all(|b| b.is_ascii_alphabetic() || (index > 0 && b.is_ascii_digit()))
+    })
+}
+
+fn iri(value: &str) -> Result<&str, String> {
+    if value.contains(is_invalid_iri_char) {
+        return Err(format!("invalid IRI: {}", value));
+    }
+    Ok(value)
+}
+
+/// Returns the N-Triples line of a statement map returned by the script.
+fn triple_line(map: &Map) -> Result<String, String> {
+    let subject_value = required(map, "subject")?;
+    let subject_type = string(map, "subject_type")?;
+    let predicate = required(map, "predicate")?;
+    let object = required(map, "object")?;
+    let object_type = string(map, "object_type")?;
+    let lang = string(map, "lang")?;
+    let data_type = string(map, "datatype")?;
+    let object = match object_type.as_deref() {
+        Some("literal") => {
+            try_unescape(&object)?;
+            Object::Literal(
+                &object,
+                match (lang.as_deref(), data_type.as_deref()) {
+                    (Some(lang), _) if is_lang(lang) => Extra::Lang(lang),
+                    (Some(lang), _) => return Err(format!("invalid language: {}", lang)),
+                    (None, Some(data_type)) => Extra::Type(iri(data_type)?),
+                    (None, None) => Extra::None,
+                },
+            )
+        }
+        kind => match subject(&object, kind)? {
+            Subject::IRI(iri) => Object::IRI(iri),
+            Subject::Blank(blank) => Object::Blank(blank),
+        },
+    };
+    let statement = Statement {
+        subject: subject(&subject_value, subject_type.as_deref())?,
+        predicate: iri(&predicate)?,
+        object,
+    };
+    Ok(format!("{}\n", statement))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{parse, RE};
+    use pretty_assertions::assert_eq;
+
+    fn run(script: &Script, line: &str) -> String {
+        let mut output = Vec::new();
+        script
+            .write(&mut output, line, parse(1, line, &RE))
+            .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_script() {
+        let script = Script::compile(
+            r#"
+            fn transform(s) {
+                if s.predicate == "http://www.wikidata.org/prop/direct/P18" {
+                    return false;
+                }
+                if s.predicate == "http://www.wikidata.org/prop/direct/P279" {
+                    let inverse = #{
+                        subject: s.object,
+                        predicate: "http://example.com/superclassOf",
+                        object: s.subject
+                    };
+                    return [s, inverse];
+                }
+                if s.lang == "en" {
+                    s.lang = "en-gb";
+                    return s;
+                }
+                true
+            }
+            "#,
+        )
+        .unwrap();
+
+        let line = "<http://www.wikidata.org/entity/Q8> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q9415> .\n";
+        assert_eq!(run(&script, line), line);
+        assert_eq!(
+            run(
+                &script,
+                "<http://www.wikidata.org/entity/Q8> <http://www.wikidata.org/prop/direct/P18> <http://commons.wikimedia.org/wiki/Special:FilePath/Happiness%20.jpg> .\n"
+            ),
+            ""
+        );
+        assert_eq!(
+            run(
+                &script,
+                "<http://www.wikidata.org/entity/Q8> <http://www.wikidata.org/prop/direct/P279> <http://www.wikidata.org/entity/Q16748867> .\n"
+            ),
+            "<http://www.wikidata.org/entity/Q8> <http://www.wikidata.org/prop/direct/P279> <http://www.wikidata.org/entity/Q16748867> .\n\
+             <http://www.wikidata.org/entity/Q16748867> <http://example.com/superclassOf> <http://www.wikidata.org/entity/Q8> .\n"
+        );
+        assert_eq!(
+            run(
+                &script,
+                "<http://www.wikidata.org/entity/Q8> <http://schema.org/name> \"happiness\"@en .\n"
+            ),
+            "<http://www.wikidata.org/entity/Q8> <http://schema.org/name> \"happiness\"@en-gb .\n"
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = Script::compile("fn transform(s) {\n  s.predicate ==\n}").err();
+        assert!(error.unwrap().contains("line 3"));
+        assert_eq!(
+            Script::compile("fn other(s) { true }").err(),
+            Some("missing function transform(statement)".to_string())
+        );
+
+        let script = Script::compile(
+            r#"
+            fn transform(s) {
+                if s.predicate == "http://schema.org/name" {
+                    s.object = "stray \\ backslash";
+                    return s;
+                }
+                if s.predicate == "http://schema.org/description" {
+                    s.object_type = "iri";
+                    s.object = "http://example.com/a b";
+                    return s;
+                }
+                s.unknown()
+            }
+            "#,
+        )
+        .unwrap();
+        let error = |line| {
+            script
+                .write(&mut Vec::new(), line, parse(1, line, &RE))
+                .unwrap_err()
+        };
+        let name = error(
+            r#"<http://www.wikidata.org/entity/Q8> <http://schema.org/name> "happiness"@en ."#,
+        );
+        assert!(name.starts_with("invalid result of transform (line 2): invalid escape"));
+        assert_eq!(
+            error(
+                r#"<http://www.wikidata.org/entity/Q8> <http://schema.org/description> "state of well-being"@en ."#
+            ),
+            "invalid result of transform (line 2): invalid IRI: http://example.com/a b"
+        );
+        assert!(error(
+            r#"<http://www.wikidata.org/entity/Q8> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q9415> ."#
+        )
+        .contains("line 12"));
+    }
+}
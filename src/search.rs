@@ -0,0 +1,290 @@
+use crate::{direct_property, entity, languages, term, unescape, Object, Statement, Term};
+use clap::Args;
+use std::collections::HashMap;
+use std::fs::create_dir_all;
+use std::io::{stdout, Write};
+use tantivy::collector::TopDocs;
+use tantivy::query::QueryParser;
+use tantivy::schema::{Field, Schema, Value, FAST, STORED, STRING, TEXT};
+use tantivy::{DocId, Index, IndexWriter, Score, SegmentReader, TantivyDocument};
+
+const WRITER_MEMORY: usize = 1_000_000_000;
+
+#[derive(Args)]
+pub struct SearchOpts {
+    /// Index directory written with `--index`
+    #[clap(long)]
+    index: String,
+    #[clap(long, default_value = "en")]
+    lang: String,
+    #[clap(short = 'n', long, default_value = "10")]
+    limit: usize,
+    query: String,
+}
+
+struct LanguageFields {
+    label: Field,
+    alias: Field,
+    description: Field,
+}
+
+pub struct SearchIndex {
+    writer: IndexWriter,
+    id: Field,
+    statements: Field,
+    languages: HashMap<&'static str, LanguageFields>,
+}
+
+fn schema() -> Schema {
+    let mut builder = Schema::builder();
+    builder.add_text_field("id", STRING | STORED);
+    builder.add_u64_field("statements", FAST | STORED);
+    let mut languages: Vec<_> = languages().exact().collect();
+    languages.sort();
+    for lang in languages {
+        builder.add_text_field(&format!("label_{}", lang), TEXT | STORED);
+        builder.add_text_field(&format!("alias_{}", lang), TEXT | STORED);
+        builder.add_text_field(&format!("description_{}", lang), TEXT | STORED);
+    }
+    builder.build()
+}
+
+fn language_fields(schema: &Schema, lang: &str) -> Option<LanguageFields> {
+    Some(LanguageFields {
+        label: schema.get_field(&format!("label_{}", lang)).ok()?,
+        alias: schema.get_field(&format!("alias_{}", lang)).ok()?,
+        description: schema.get_field(&format!("description_{}", lang)).ok()?,
+    })
+}
+
+impl SearchIndex {
+    pub fn create(path: &str) -> SearchIndex {
+        create_dir_all(path).unwrap_or_else(|_| panic!("unable to create directory: {}", path));
+        let schema = schema();
+        let index = Index::create_in_dir(path, schema.clone())
+            .unwrap_or_else(|err| panic!("unable to create index in {}: {}", path, err));
+        let writer = index
+            .writer(WRITER_MEMORY)
+            .expect("failed to create index writer");
+        let languages = languages()
+            .exact()
+            .map(|lang| (lang, language_fields(&schema, lang).unwrap()))
+            .collect();
+        SearchIndex {
+            writer,
+            id: schema.get_field("id").unwrap(),
+            statements: schema.get_field("statements").unwrap(),
+            languages,
+        }
+    }
+
+    /// Adds one document for the entity described by the given statements,
+    /// which must all belong to the same entity block.
+    pub fn add(&self, statements: &[Statement]) -> Option<()> {
+        let id = statements
+            .iter()
+            .find_map(|statement| entity(statement.subject))?;
+
+        let mut document = TantivyDocument::new();
+        let mut count = 0;
+        let mut has_text = false;
+
+        for statement in statements {
+            if entity(statement.subject) != Some(id) {
+                continue;
+            }
+            if direct_property(statement.predicate).is_some() {
+                count += 1;
+                continue;
+            }
+            let (Some((term, lang)), Object::Literal(text, _)) =
+                (term(*statement), statement.object)
+            else {
+                continue;
+            };
+            let Some(fields) = self.languages.get(lang.to_ascii_lowercase().as_str()) else {
+                continue;
+            };
+            let field = match term {
+                Term::Label => fields.label,
+                Term::Alias => fields.alias,
+                Term::Description => fields.description,
+            };
+            document.add_text(field, unescape(text));
+            has_text = true;
+        }
+
+        if !has_text {
+            return None;
+        }
+
+        document.add_text(self.id, id.to_string());
+        document.add_u64(self.statements, count);
+        self.writer
+            .add_document(document)
+            .expect("failed to add document to index");
+        None
+    }
+
+    pub fn commit(mut self) {
+        self.writer.commit().expect("failed to commit index");
+        self.writer
+            .wait_merging_threads()
+            .expect("failed to merge index segments");
+    }
+}
+
+pub fn search(opts: SearchOpts) {
+    write_results(&opts, &mut stdout().lock());
+}
+
+/// Writes the results of the search, one per line:
+/// entity, score, label and description, separated by tabs.
+fn write_results<W: Write>(opts: &SearchOpts, writer: &mut W) {
+    let index = Index::open_in_dir(&opts.index)
+        .unwrap_or_else(|err| panic!("unable to open index {}: {}", opts.index, err));
+    let schema = index.schema();
+    let fields = language_fields(&schema, &opts.lang.to_ascii_lowercase())
+        .unwrap_or_else(|| panic!("language not indexed: {}", opts.lang));
+    let id = schema.get_field("id").unwrap();
+
+    let mut parser =
+        QueryParser::for_index(&index, vec![fields.label, fields.alias, fields.description]);
+    parser.set_field_boost(fields.label, 3.0);
+    parser.set_field_boost(fields.alias, 2.0);
+    for field in [fields.label, fields.alias, fields.description] {
+        parser.set_field_fuzzy(field, false, 1, true);
+    }
+    let query = parser
+        .parse_query(&opts.query)
+        .unwrap_or_else(|err| panic!("invalid query {:?}: {}", opts.query, err));
+
+    // rank by text relevance, boosted by how well described the entity is
+    let collector =
+        TopDocs::with_limit(opts.limit).tweak_score(move |segment_reader: &SegmentReader| {
+            let statements = segment_reader
+                .fast_fields()
+                .u64("statements")
+                .unwrap()
+                .first_or_default_col(0);
+            move |doc: DocId, score: Score| score * (1.0 + (statements.get_val(doc) as f32).ln_1p())
+        });
+
+    let searcher = index
+        .reader()
+        .expect("failed to open index reader")
+        .searcher();
+    let results = searcher
+        .search(&query, &collector)
+        .expect("failed to search index");
+
+    for (score, address) in results {
+        let document: TantivyDocument = searcher.doc(address).expect("failed to load document");
+        let text = |field| {
+            document
+                .get_first(field)
+                .and_then(|value| value.as_str())
+                .unwrap_or("")
+                .to_string()
+        };
+        writeln!(
+            writer,
+            "{}\t{:.3}\t{}\t{}",
+            text(id),
+            score,
+            text(fields.label),
+            text(fields.description)
+        )
+        .unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::tests::statements;
+
+    #[test]
+    fn test_index_and_search() {
+        let dir =
+            std::env::temp_dir().join(format!("wikidata-filter-index-{}", std::process::id()));
+        let path = dir.to_str().unwrap();
+
+        let index = SearchIndex::create(path);
+        for lines in [
+            &[
+                r#"<http://www.wikidata.org/entity/Q8> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q331769> ."#,
+                r#"<http://www.wikidata.org/entity/Q8> <http://www.w3.org/2000/01/rdf-schema#label> "happiness"@en ."#,
+                r#"<http://www.wikidata.org/entity/Q8> <http://www.w3.org/2004/02/skos/core#altLabel> "joy"@en ."#,
+                r#"<http://www.wikidata.org/entity/Q8> <http://schema.org/description> "mental or emotional state"@en ."#,
+            ][..],
+            &[
+                r#"<http://www.wikidata.org/entity/Q1> <http://www.w3.org/2000/01/rdf-schema#label> "contentment"@en ."#,
+                r#"<http://www.wikidata.org/entity/Q1> <http://schema.org/description> "state of happiness"@en ."#,
+            ][..],
+            &[
+                r#"<http://www.wikidata.org/entity/Q2> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q5> ."#,
+                r#"<http://www.wikidata.org/entity/Q2> <http://www.wikidata.org/prop/direct/P21> <http://www.wikidata.org/entity/Q6581072> ."#,
+                r#"<http://www.wikidata.org/entity/Q2> <http://www.w3.org/2000/01/rdf-schema#label> "Joy"@en ."#,
+            ][..],
+            &[
+                r#"<http://www.wikidata.org/entity/Q3> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q331769> ."#,
+                r#"<http://www.wikidata.org/entity/Q3> <http://www.w3.org/2000/01/rdf-schema#label> "contentment"@en ."#,
+            ][..],
+        ] {
+            let statements = statements(lines);
+            index.add(&statements);
+        }
+        index.commit();
+
+        let search = |query: &str| {
+            let mut output = Vec::new();
+            write_results(
+                &SearchOpts {
+                    index: path.to_string(),
+                    lang: "en".to_string(),
+                    limit: 10,
+                    query: query.to_string(),
+                },
+                &mut output,
+            );
+            let output = String::from_utf8(output).unwrap();
+            output
+                .lines()
+                .map(|line| {
+                    let fields: Vec<_> = line.split('\t').collect();
+                    (fields[0].to_string(), fields[2].to_string())
+                })
+                .collect::<Vec<_>>()
+        };
+
+        // fuzzy, and the label ranks before the description
+        assert_eq!(
+            search("hapiness"),
+            vec![
+                ("Q8".to_string(), "happiness".to_string()),
+                ("Q1".to_string(), "contentment".to_string())
+            ]
+        );
+        // the label ranks before the alias
+        assert_eq!(
+            search("joy"),
+            vec![
+                ("Q2".to_string(), "Joy".to_string()),
+                ("Q8".to_string(), "happiness".to_string())
+            ]
+        );
+
+        // equal matches rank by the number of statements
+        assert_eq!(
+            search("contentment"),
+            vec![
+                ("Q3".to_string(), "contentment".to_string()),
+                ("Q1".to_string(), "contentment".to_string())
+            ]
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,238 @@
+use crate::entity::EntityId;
+use crate::label_store::{Label, LabelStore};
+use crate::Term;
+use clap::Args;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+#[derive(Args)]
+pub struct ServeOpts {
+    /// Label store written with `--label-store`
+    #[clap(long)]
+    store: String,
+    #[clap(short, long, default_value = "8080")]
+    port: u16,
+}
+
+fn parse_id(id: &str) -> Option<u64> {
+    EntityId::parse(id).map(EntityId::key)
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        match bytes[index] {
+            b'%' if index + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[index + 1..index + 3]).unwrap_or("");
+                match u8::from_str_radix(hex, 16) {
+                    Ok(byte) => {
+                        decoded.push(byte);
+                        index += 3;
+                        continue;
+                    }
+                    Err(_) => decoded.push(b'%'),
+                }
+            }
+            b'+' => decoded.push(b' '),
+            byte => decoded.push(byte),
+        }
+        index += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn parse_query(query: &str) -> HashMap<String, String> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(key), percent_decode(value))
+        })
+        .collect()
+}
+
+fn find<'a>(store: &'a LabelStore, id: u64, term: Term, lang: &str) -> Option<Label<'a>> {
+    store
+        .get(id)
+        .find(|label| label.term == term && label.lang == lang)
+}
+
+fn labels(store: &LabelStore, ids: &[String], lang: &str) -> Value {
+    let labels: Map<String, Value> = ids
+        .iter()
+        .map(|id| {
+            let label = parse_id(id)
+                .and_then(|number| find(store, number, Term::Label, lang))
+                .map(|label| Value::from(label.text))
+                .unwrap_or(Value::Null);
+            (id.clone(), label)
+        })
+        .collect();
+    json!({ "lang": lang, "labels": labels })
+}
+
+fn entity(store: &LabelStore, id: &str, term: Term, lang: &str) -> (u16, Value) {
+    let Some(number) = parse_id(id) else {
+        return (400, json!({ "error": format!("invalid entity: {}", id) }));
+    };
+    let key = match term {
+        Term::Label => "label",
+        Term::Alias => "alias",
+        Term::Description => "description",
+    };
+    match find(store, number, term, lang) {
+        Some(label) => (200, json!({ "id": id, "lang": lang, key: label.text })),
+        None => (
+            404,
+            json!({ "error": format!("no {} for {} in {}", key, id, lang) }),
+        ),
+    }
+}
+
+/// Answers the request and returns the response status and JSON body. Supported are:
+///
+/// - `GET /entity/Q42/label?lang=en` (and `/description`)
+/// - `GET /labels?ids=Q1,Q42&lang=en`
+/// - `POST /labels` with a body like `{"ids": ["Q1", "Q42"], "lang": "en"}`
+fn respond(store: &LabelStore, method: &Method, url: &str, body: &str) -> (u16, Value) {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let query = parse_query(query);
+    let lang = query.get("lang").map(String::as_str).unwrap_or("en");
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+
+    match (method, segments.as_slice()) {
+        (Method::Get, ["entity", id, "label"]) => entity(store, id, Term::Label, lang),
+        (Method::Get, ["entity", id, "description"]) => entity(store, id, Term::Description, lang),
+        (Method::Get, ["labels"]) => {
+            let ids: Vec<String> = query
+                .get("ids")
+                .map(|ids| ids.split(',').map(str::to_string).collect())
+                .unwrap_or_default();
+            (200, labels(store, &ids, lang))
+        }
+        (Method::Post, ["labels"]) => {
+            let Ok(request) = serde_json::from_str::<Value>(body) else {
+                return (400, json!({ "error": "invalid JSON body" }));
+            };
+            let ids: Vec<String> = request["ids"]
+                .as_array()
+                .map(|ids| {
+                    ids.iter()
+                        .filter_map(|id| id.as_str().map(str::to_string))
+                        .collect()
+                })
+                .unwrap_or_default();
+            let lang = request["lang"].as_str().unwrap_or(lang);
+            (200, labels(store, &ids, lang))
+        }
+        _ => (404, json!({ "error": "not found" })),
+    }
+}
+
+fn handle_request(store: &LabelStore, mut request: Request) {
+    let mut body = String::new();
+    if request.as_reader().read_to_string(&mut body).is_err() {
+        body.clear();
+    }
+    let (status, value) = respond(store, request.method(), request.url(), &body);
+    let response = Response::from_string(value.to_string())
+        .with_status_code(status)
+        .with_header(Header::from_bytes("Content-Type", "application/json").unwrap());
+    if let Err(err) = request.respond(response) {
+        eprintln!("# failed to respond: {}", err);
+    }
+}
+
+pub fn serve(opts: ServeOpts) {
+    let store = LabelStore::open(&opts.store).unwrap_or_else(|err| panic!("{}", err));
+    let address = ("127.0.0.1", opts.port);
+    let server = Server::http(address)
+        .unwrap_or_else(|err| panic!("unable to listen on port {}: {}", opts.port, err));
+    eprintln!("# serving {} on http://127.0.0.1:{}", opts.store, opts.port);
+
+    for request in server.incoming_requests() {
+        handle_request(&store, request);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::label_store::{maybe_add_label_entry, write_label_store, LabelRuns, RUN_ENTRIES};
+    use crate::{entity, parse, RE};
+
+    #[test]
+    fn test_parse_query() {
+        let query = parse_query("ids=Q1%2CQ42&lang=en");
+        assert_eq!(query.get("ids").map(String::as_str), Some("Q1,Q42"));
+        assert_eq!(query.get("lang").map(String::as_str), Some("en"));
+    }
+
+    #[test]
+    fn test_respond() {
+        let path =
+            std::env::temp_dir().join(format!("wikidata-filter-{}.serve", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let mut runs = LabelRuns::new(path, "1", RUN_ENTRIES);
+        for line in [
+            r#"<http://www.wikidata.org/entity/Q42> <http://www.w3.org/2000/01/rdf-schema#label> "Douglas Adams"@en ."#,
+            r#"<http://www.wikidata.org/entity/Q42> <http://schema.org/description> "English writer"@en ."#,
+            r#"<http://www.wikidata.org/entity/Q1> <http://www.w3.org/2000/01/rdf-schema#label> "universe"@en ."#,
+        ] {
+            let statement = parse(1, line, &RE);
+            maybe_add_label_entry(
+                Some(&mut runs),
+                entity(statement.subject).unwrap(),
+                statement,
+            );
+        }
+        write_label_store(path, &runs.finish());
+        let store = LabelStore::open(path).unwrap();
+
+        assert_eq!(
+            respond(&store, &Method::Get, "/entity/Q42/label", ""),
+            (
+                200,
+                json!({"id": "Q42", "lang": "en", "label": "Douglas Adams"})
+            )
+        );
+        assert_eq!(
+            respond(&store, &Method::Get, "/entity/Q42/description?lang=en", ""),
+            (
+                200,
+                json!({"id": "Q42", "lang": "en", "description": "English writer"})
+            )
+        );
+        assert_eq!(
+            respond(&store, &Method::Get, "/entity/Q42/label?lang=de", "").0,
+            404
+        );
+        assert_eq!(respond(&store, &Method::Get, "/entity/X1/label", "").0, 400);
+        assert_eq!(
+            respond(&store, &Method::Get, "/labels?ids=Q1,Q42,Q2&lang=en", ""),
+            (
+                200,
+                json!({"lang": "en", "labels": {"Q1": "universe", "Q42": "Douglas Adams", "Q2": null}})
+            )
+        );
+        assert_eq!(
+            respond(
+                &store,
+                &Method::Post,
+                "/labels",
+                r#"{"ids": ["Q1"], "lang": "de"}"#
+            ),
+            (200, json!({"lang": "de", "labels": {"Q1": null}}))
+        );
+        assert_eq!(respond(&store, &Method::Post, "/labels", "{").0, 400);
+        assert_eq!(respond(&store, &Method::Get, "/entities", "").0, 404);
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
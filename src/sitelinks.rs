@@ -0,0 +1,194 @@
+use crate::entity::{EntityId, ENTITY_DATA_IRI_PREFIX};
+use crate::{unescape, Extra, Object, Statement, Subject};
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+
+static ABOUT_IRI: &str = "http://schema.org/about";
+static IS_PART_OF_IRI: &str = "http://schema.org/isPartOf";
+static NAME_IRI: &str = "http://schema.org/name";
+static SITELINKS_IRI: &str = "http://wikiba.se/ontology#sitelinks";
+
+#[derive(Debug, PartialEq, Eq)]
+struct Sitelink<'a> {
+    url: &'a str,
+    entity: EntityId,
+    site: Option<&'a str>,
+    title: Option<String>,
+}
+
+/// Returns the sitelinks of an entity block, in the order of their `schema:about` triples.
+/// The dump describes each sitelink with triples about the article,
+/// which are listed in the block of the entity they link.
+fn sitelinks<'a>(statements: &[Statement<'a>]) -> Vec<Sitelink<'a>> {
+    let mut sitelinks: Vec<Sitelink> = Vec::new();
+    let mut indices: HashMap<&str, usize> = HashMap::new();
+    for statement in statements {
+        let Subject::IRI(url) = statement.subject else {
+            continue;
+        };
+        if statement.predicate == ABOUT_IRI {
+            if url.starts_with(ENTITY_DATA_IRI_PREFIX) {
+                continue;
+            }
+            let Object::IRI(iri) = statement.object else {
+                continue;
+            };
+            if let Some(entity) = EntityId::from_iri(iri) {
+                indices.insert(url, sitelinks.len());
+                sitelinks.push(Sitelink {
+                    url,
+                    entity,
+                    site: None,
+                    title: None,
+                });
+            }
+            continue;
+        }
+        let Some(&index) = indices.get(url) else {
+            continue;
+        };
+        let sitelink = &mut sitelinks[index];
+        match statement.object {
+            Object::IRI(site) if statement.predicate == IS_PART_OF_IRI => {
+                sitelink.site = Some(site)
+            }
+            Object::Literal(title, Extra::Lang(_)) if statement.predicate == NAME_IRI => {
+                sitelink.title = Some(unescape(title))
+            }
+            _ => (),
+        }
+    }
+    sitelinks
+}
+
+/// Returns the database name of the wiki with the given site URL,
+/// e.g. `enwiki` for `https://en.wikipedia.org/` or `commonswiki` for `https://commons.wikimedia.org/`.
+fn wiki_id(site: &str) -> Option<String> {
+    let host = site
+        .strip_prefix("https://")
+        .or_else(|| site.strip_prefix("http://"))?
+        .trim_end_matches('/');
+    let (language, domain) = host.split_once('.')?;
+    let language = language.replace('-', "_");
+    let id = match domain {
+        "wikipedia.org" => format!("{}wiki", language),
+        "wikimedia.org" => format!("{}wiki", language),
+        "wikidata.org" => "wikidatawiki".to_string(),
+        "mediawiki.org" => "mediawikiwiki".to_string(),
+        "wikisource.org" if language == "www" => "sourceswiki".to_string(),
+        _ => {
+            let project = domain.strip_suffix(".org")?;
+            if project.contains('.') {
+                return None;
+            }
+            format!("{}{}", language, project)
+        }
+    };
+    Some(id)
+}
+
+/// Returns the wikis an entity block has sitelinks to.
+pub fn sitelink_wikis(statements: &[Statement]) -> Vec<String> {
+    sitelinks(statements)
+        .into_iter()
+        .filter_map(|sitelink| wiki_id(sitelink.site?))
+        .collect()
+}
+
+/// Returns the number of sitelinks of an entity block, as stated by its `wikibase:sitelinks` triple,
+/// or else counted from its `schema:about` triples.
+pub fn sitelink_count(statements: &[Statement]) -> u64 {
+    statements
+        .iter()
+        .find_map(|statement| match statement.object {
+            Object::Literal(count, _) if statement.predicate == SITELINKS_IRI => count.parse().ok(),
+            _ => None,
+        })
+        .unwrap_or_else(|| sitelinks(statements).len() as u64)
+}
+
+/// Writes one tab-separated row per sitelink of an entity block: entity, wiki, title and URL.
+/// Only sitelinks to the given wikis are written, or all if none are given.
+pub fn maybe_write_sitelinks<T: Write>(
+    sitelinks_writer: Option<&mut T>,
+    wikis: &HashSet<String>,
+    statements: &[Statement],
+) -> Option<()> {
+    let sitelinks_writer = sitelinks_writer?;
+    for sitelink in sitelinks(statements) {
+        let (Some(site), Some(title)) = (sitelink.site, sitelink.title) else {
+            continue;
+        };
+        let Some(wiki) = wiki_id(site) else {
+            continue;
+        };
+        if !wikis.is_empty() && !wikis.contains(&wiki) {
+            continue;
+        }
+        sitelinks_writer
+            .write_fmt(format_args!(
+                "{}\t{}\t{}\t{}\n",
+                sitelink.entity, wiki, title, sitelink.url
+            ))
+            .unwrap();
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::tests::statements;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_wiki_id() {
+        for (site, id) in [
+            ("https://en.wikipedia.org/", "enwiki"),
+            ("https://zh-min-nan.wikipedia.org/", "zh_min_nanwiki"),
+            ("https://de.wikiquote.org/", "dewikiquote"),
+            ("https://fr.wiktionary.org/", "frwiktionary"),
+            ("https://commons.wikimedia.org/", "commonswiki"),
+            ("https://www.wikidata.org/", "wikidatawiki"),
+        ] {
+            assert_eq!(wiki_id(site).as_deref(), Some(id));
+        }
+        assert_eq!(wiki_id("https://example.com/"), None);
+    }
+
+    #[test]
+    fn test_sitelinks() {
+        let statements = statements(&[
+            r#"<https://www.wikidata.org/wiki/Special:EntityData/Q42> <http://schema.org/about> <http://www.wikidata.org/entity/Q42> ."#,
+            r#"<https://en.wikipedia.org/wiki/Douglas_Adams> <http://schema.org/about> <http://www.wikidata.org/entity/Q42> ."#,
+            r#"<https://en.wikipedia.org/wiki/Douglas_Adams> <http://schema.org/inLanguage> "en" ."#,
+            r#"<https://en.wikipedia.org/wiki/Douglas_Adams> <http://schema.org/isPartOf> <https://en.wikipedia.org/> ."#,
+            r#"<https://en.wikipedia.org/wiki/Douglas_Adams> <http://schema.org/name> "Douglas Adams"@en ."#,
+            r#"<https://en.wikipedia.org/> <http://wikiba.se/ontology#wikiGroup> "wikipedia" ."#,
+            r#"<https://de.wikiquote.org/wiki/Douglas_Adams> <http://schema.org/about> <http://www.wikidata.org/entity/Q42> ."#,
+            r#"<https://de.wikiquote.org/wiki/Douglas_Adams> <http://schema.org/isPartOf> <https://de.wikiquote.org/> ."#,
+            r#"<https://de.wikiquote.org/wiki/Douglas_Adams> <http://schema.org/name> "Douglas Adams"@de ."#,
+            r#"<http://www.wikidata.org/entity/Q42> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q5> ."#,
+        ]);
+
+        let mut output = Vec::new();
+        maybe_write_sitelinks(Some(&mut output), &HashSet::new(), &statements);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Q42\tenwiki\tDouglas Adams\thttps://en.wikipedia.org/wiki/Douglas_Adams\n\
+             Q42\tdewikiquote\tDouglas Adams\thttps://de.wikiquote.org/wiki/Douglas_Adams\n"
+        );
+
+        assert_eq!(sitelink_wikis(&statements), vec!["enwiki", "dewikiquote"]);
+        assert_eq!(sitelink_count(&statements), 2);
+
+        let mut output = Vec::new();
+        let wikis = HashSet::from(["enwiki".to_string()]);
+        maybe_write_sitelinks(Some(&mut output), &wikis, &statements);
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Q42\tenwiki\tDouglas Adams\thttps://en.wikipedia.org/wiki/Douglas_Adams\n"
+        );
+    }
+}
@@ -0,0 +1,208 @@
+use crate::entity::EntityId;
+use crate::{term, unescape, Extra, Object, Statement, Term};
+use crossbeam_channel::{bounded, Receiver, Sender};
+use rusqlite::{params, Connection};
+use std::path::Path;
+use std::thread::{self, JoinHandle};
+
+pub const BATCH_ROWS: usize = 10_000;
+
+const SCHEMA: &str = "
+    CREATE TABLE labels (entity TEXT NOT NULL, lang TEXT NOT NULL, label TEXT NOT NULL);
+    CREATE TABLE aliases (entity TEXT NOT NULL, lang TEXT NOT NULL, alias TEXT NOT NULL);
+    CREATE TABLE descriptions (entity TEXT NOT NULL, lang TEXT NOT NULL, description TEXT NOT NULL);
+    CREATE TABLE statement_counts (entity TEXT NOT NULL, count INTEGER NOT NULL);
+";
+
+// indices are only created once all rows are inserted, which is much faster than maintaining them
+const INDICES: &str = "
+    CREATE INDEX labels_entity ON labels (entity, lang);
+    CREATE INDEX labels_label ON labels (label);
+    CREATE INDEX aliases_entity ON aliases (entity, lang);
+    CREATE INDEX aliases_alias ON aliases (alias);
+    CREATE INDEX descriptions_entity ON descriptions (entity, lang);
+    CREATE UNIQUE INDEX statement_counts_entity ON statement_counts (entity);
+    CREATE INDEX statement_counts_count ON statement_counts (count);
+";
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Row {
+    Label(String, String, String),
+    Alias(String, String, String),
+    Description(String, String, String),
+    StatementCount(String, u64),
+}
+
+pub struct SqliteWriter {
+    sender: Sender<Vec<Row>>,
+    thread: JoinHandle<()>,
+}
+
+impl SqliteWriter {
+    /// Starts the single thread which owns the database connection
+    /// and inserts the batches of rows sent by the consumers.
+    /// The database must not exist yet, as a second run would duplicate all rows.
+    pub fn spawn(path: &str) -> SqliteWriter {
+        if Path::new(path).exists() {
+            panic!("database {} already exists, remove it first", path);
+        }
+        let connection = Connection::open(path)
+            .unwrap_or_else(|err| panic!("unable to open database {}: {}", path, err));
+        connection
+            .execute_batch("PRAGMA journal_mode = OFF; PRAGMA synchronous = OFF;")
+            .expect("failed to configure database");
+        connection
+            .execute_batch(SCHEMA)
+            .expect("failed to create database tables");
+
+        let (sender, receiver) = bounded(64);
+        let thread = thread::spawn(move || write(connection, receiver));
+        SqliteWriter { sender, thread }
+    }
+
+    pub fn sender(&self) -> Sender<Vec<Row>> {
+        self.sender.clone()
+    }
+
+    /// Waits for all sent rows to be written and indexes the tables.
+    pub fn finish(self) {
+        drop(self.sender);
+        self.thread.join().unwrap();
+    }
+}
+
+fn write(mut connection: Connection, receiver: Receiver<Vec<Row>>) {
+    for rows in receiver.iter() {
+        let transaction = connection.transaction().unwrap();
+        {
+            let mut insert_label = transaction
+                .prepare_cached("INSERT INTO labels VALUES (?1, ?2, ?3)")
+                .unwrap();
+            let mut insert_alias = transaction
+                .prepare_cached("INSERT INTO aliases VALUES (?1, ?2, ?3)")
+                .unwrap();
+            let mut insert_description = transaction
+                .prepare_cached("INSERT INTO descriptions VALUES (?1, ?2, ?3)")
+                .unwrap();
+            let mut insert_statement_count = transaction
+                .prepare_cached("INSERT INTO statement_counts VALUES (?1, ?2)")
+                .unwrap();
+            for row in rows {
+                match row {
+                    Row::Label(entity, lang, label) => {
+                        insert_label.execute(params![entity, lang, label])
+                    }
+                    Row::Alias(entity, lang, alias) => {
+                        insert_alias.execute(params![entity, lang, alias])
+                    }
+                    Row::Description(entity, lang, description) => {
+                        insert_description.execute(params![entity, lang, description])
+                    }
+                    Row::StatementCount(entity, count) => {
+                        insert_statement_count.execute(params![entity, count])
+                    }
+                }
+                .expect("failed to insert row");
+            }
+        }
+        transaction.commit().expect("failed to commit rows");
+    }
+
+    eprintln!("# indexing database");
+    connection
+        .execute_batch(INDICES)
+        .expect("failed to create database indices");
+}
+
+pub fn maybe_add_row(
+    rows: Option<&mut Vec<Row>>,
+    id: EntityId,
+    statement: Statement,
+) -> Option<()> {
+    let rows = rows?;
+    let Object::Literal(text, Extra::Lang(lang)) = statement.object else {
+        return None;
+    };
+    let (term, _) = term(statement)?;
+    let (entity, lang, text) = (id.to_string(), lang.to_string(), unescape(text));
+    rows.push(match term {
+        Term::Label => Row::Label(entity, lang, text),
+        Term::Alias => Row::Alias(entity, lang, text),
+        Term::Description => Row::Description(entity, lang, text),
+    });
+    None
+}
+
+pub fn statement_count_row(key: u64, count: u64) -> Row {
+    Row::StatementCount(EntityId::from_key(key).to_string(), count)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{entity, parse, RE};
+
+    #[test]
+    fn test_rows() {
+        let mut rows = Vec::new();
+        for line in [
+            r#"<http://www.wikidata.org/entity/Q8> <http://www.w3.org/2000/01/rdf-schema#label> "happiness"@en ."#,
+            r#"<http://www.wikidata.org/entity/Q8> <http://www.w3.org/2000/01/rdf-schema#label> "bonheur"@fr ."#,
+            r#"<http://www.wikidata.org/entity/Q8> <http://www.w3.org/2004/02/skos/core#altLabel> "joy"@en ."#,
+            r#"<http://www.wikidata.org/entity/Q8> <http://schema.org/description> "state of well-being"@en ."#,
+            r#"<http://www.wikidata.org/entity/Q8> <http://www.wikidata.org/prop/direct/P373> "Happiness" ."#,
+        ] {
+            let statement = parse(1, line, &RE);
+            maybe_add_row(
+                Some(&mut rows),
+                entity(statement.subject).unwrap(),
+                statement,
+            );
+        }
+        assert_eq!(
+            rows,
+            vec![
+                Row::Label("Q8".into(), "en".into(), "happiness".into()),
+                Row::Alias("Q8".into(), "en".into(), "joy".into()),
+                Row::Description("Q8".into(), "en".into(), "state of well-being".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_write() {
+        let path = std::env::temp_dir().join(format!("wikidata-filter-{}.db", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let writer = SqliteWriter::spawn(path);
+        writer
+            .sender()
+            .send(vec![
+                Row::Label("Q8".into(), "en".into(), "happiness".into()),
+                Row::Alias("Q8".into(), "en".into(), "joy".into()),
+                statement_count_row(8, 42),
+            ])
+            .unwrap();
+        writer.finish();
+
+        let connection = Connection::open(path).unwrap();
+        let count: u64 = connection
+            .query_row(
+                "SELECT count FROM statement_counts JOIN labels USING (entity) WHERE label = ?1",
+                ["happiness"],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(count, 42);
+        let alias: String = connection
+            .query_row("SELECT alias FROM aliases WHERE entity = 'Q8'", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(alias, "joy");
+
+        assert!(std::panic::catch_unwind(|| SqliteWriter::spawn(path)).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,115 @@
+use crate::entity::EntityId;
+use crate::{block_key, entity, parse, scan_lines, Object, Statement, RE};
+use roaring::RoaringTreemap;
+
+/// Adds the entities the statement links to, if its subject is in the frontier.
+fn maybe_add_neighbours(
+    frontier: &RoaringTreemap,
+    next: &mut RoaringTreemap,
+    statement: Statement,
+) -> Option<()> {
+    let subject = entity(statement.subject)?;
+    if !frontier.contains(subject.key()) {
+        return None;
+    }
+    let Object::IRI(iri) = statement.object else {
+        return None;
+    };
+    next.insert(EntityId::from_iri(iri)?.key());
+    None
+}
+
+/// Returns the keys of the seeds and all entities within the given number of hops from them,
+/// following the links from subjects to entity objects.
+/// Each hop is a pass over the dumps, which only expands the entities added by the previous hop.
+pub fn read_subgraph(paths: &[String], seeds: &[EntityId], hops: usize) -> RoaringTreemap {
+    let mut entities: RoaringTreemap = seeds.iter().map(|seed| seed.key()).collect();
+    let mut frontier = entities.clone();
+    for hop in 1..=hops {
+        if frontier.is_empty() {
+            break;
+        }
+        let mut next = RoaringTreemap::new();
+        scan_lines(paths, |number, line| {
+            let Some(subject) = block_key(line).and_then(EntityId::parse) else {
+                return;
+            };
+            if frontier.contains(subject.key()) {
+                maybe_add_neighbours(&frontier, &mut next, parse(number, line, &RE));
+            }
+        });
+        next -= &entities;
+        entities |= &next;
+        eprintln!("# hop {}: {} entities", hop, entities.len());
+        frontier = next;
+    }
+    entities
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use bzip2::write::BzEncoder;
+    use bzip2::Compression;
+    use pretty_assertions::assert_eq;
+    use std::fs::File;
+    use std::io::Write;
+
+    #[test]
+    fn test_neighbours() {
+        let frontier: RoaringTreemap = [1].into_iter().collect();
+        let mut next = RoaringTreemap::new();
+        for line in [
+            r#"<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q5> ."#,
+            r#"<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P1476> "Title"@en ."#,
+            r#"<http://www.wikidata.org/entity/Q1> <http://www.wikidata.org/prop/direct/P1343> <http://www.wikidata.org/entity/P31> ."#,
+            r#"<http://www.wikidata.org/entity/Q2> <http://www.wikidata.org/prop/direct/P31> <http://www.wikidata.org/entity/Q6> ."#,
+        ] {
+            maybe_add_neighbours(&frontier, &mut next, parse(1, line, &RE));
+        }
+        assert_eq!(
+            next.iter().map(EntityId::from_key).collect::<Vec<_>>(),
+            vec![
+                EntityId::parse("Q5").unwrap(),
+                EntityId::parse("P31").unwrap()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_subgraph() {
+        // a chain Q1 -> Q2 -> Q3 -> Q4, with Q5 -> Q1 linking into it
+        let path = std::env::temp_dir().join(format!(
+            "wikidata-filter-{}.subgraph.nt.bz2",
+            std::process::id()
+        ));
+        let path = path.to_str().unwrap().to_string();
+        let mut encoder = BzEncoder::new(File::create(&path).unwrap(), Compression::fast());
+        for (subject, object) in [(1, 2), (2, 3), (3, 4), (5, 1)] {
+            writeln!(
+                encoder,
+                "<http://www.wikidata.org/entity/Q{}> <http://www.wikidata.org/prop/direct/P361> <http://www.wikidata.org/entity/Q{}> .",
+                subject, object
+            )
+            .unwrap();
+        }
+        encoder.finish().unwrap();
+
+        let subgraph = |hops| {
+            read_subgraph(
+                std::slice::from_ref(&path),
+                &[EntityId::parse("Q1").unwrap()],
+                hops,
+            )
+            .iter()
+            .collect::<Vec<_>>()
+        };
+        assert_eq!(subgraph(0), vec![1]);
+        assert_eq!(subgraph(1), vec![1, 2]);
+        assert_eq!(subgraph(2), vec![1, 2, 3]);
+        assert_eq!(subgraph(5), vec![1, 2, 3, 4]);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}